@@ -0,0 +1,114 @@
+// Move, scale and rotation of a cue about the point it is placed at, changing over
+// its lifetime as keyframes say
+#[derive(Clone, Default)]
+pub struct Animation {
+    // Transform before any keyframe starts
+    pub base: Transform,
+    pub keyframes: Vec<Keyframe>,
+}
+
+impl Animation {
+    // Each keyframe takes its property from the value the keyframes before it left
+    pub fn at(&self, elapsed_ms: u64) -> Transform {
+        let mut transform = self.base;
+        for keyframe in &self.keyframes {
+            let t = keyframe.progress(elapsed_ms);
+            match keyframe.to {
+                Property::Offset(x, y) => {
+                    transform.offset = (
+                        lerp(transform.offset.0, x, t),
+                        lerp(transform.offset.1, y, t),
+                    );
+                }
+                Property::ScaleX(x) => transform.scale.0 = lerp(transform.scale.0, x, t),
+                Property::ScaleY(y) => transform.scale.1 = lerp(transform.scale.1, y, t),
+                Property::Rotation(degrees) => {
+                    transform.rotation = lerp(transform.rotation, degrees, t);
+                }
+            }
+        }
+        transform
+    }
+
+    pub fn animating(&self, elapsed_ms: u64) -> bool {
+        self.keyframes
+            .iter()
+            .any(|keyframe| (keyframe.start..keyframe.end).contains(&elapsed_ms))
+    }
+
+    pub fn finished(&self, elapsed_ms: u64) -> usize {
+        self.keyframes
+            .iter()
+            .filter(|keyframe| elapsed_ms >= keyframe.end)
+            .count()
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub struct Transform {
+    // Offset from where the cue is placed, in output pixels
+    pub offset: (f32, f32),
+    pub scale: (f32, f32),
+    // Degrees clockwise
+    pub rotation: f32,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self {
+            offset: (0.0, 0.0),
+            scale: (1.0, 1.0),
+            rotation: 0.0,
+        }
+    }
+}
+
+// One property moving to `to` between two times in milliseconds after the cue start
+#[derive(Clone, Copy)]
+pub struct Keyframe {
+    pub start: u64,
+    pub end: u64,
+    pub easing: Easing,
+    pub to: Property,
+}
+
+impl Keyframe {
+    fn progress(&self, elapsed_ms: u64) -> f32 {
+        if elapsed_ms >= self.end {
+            1.0
+        } else if elapsed_ms <= self.start {
+            0.0
+        } else {
+            let t = (elapsed_ms - self.start) as f32 / (self.end - self.start) as f32;
+            self.easing.apply(t)
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum Property {
+    Offset(f32, f32),
+    ScaleX(f32),
+    ScaleY(f32),
+    Rotation(f32),
+}
+
+#[derive(Clone, Copy)]
+pub enum Easing {
+    Linear,
+    // Progress raised to this power, speeding up above 1 and slowing down below it
+    Power(f32),
+}
+
+impl Easing {
+    fn apply(self, t: f32) -> f32 {
+        match self {
+            Easing::Linear => t,
+            Easing::Power(power) => t.powf(power),
+        }
+    }
+}
+
+fn lerp(from: f32, to: f32, t: f32) -> f32 {
+    from + (to - from) * t
+}
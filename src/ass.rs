@@ -0,0 +1,754 @@
+use crate::animation::{Animation, Easing, Keyframe, Property, Transform};
+use crate::input::parse_timestamp;
+use crate::subtitle::{Align, Karaoke, Line, LinePosition, Placement, Run, Style, Subtitle};
+use skia_safe::Color;
+use std::collections::HashMap;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Section {
+    Other,
+    ScriptInfo,
+    Styles,
+    Events,
+}
+
+// Style as declared in the script, before scaling onto the output frame
+#[derive(Clone)]
+struct ScriptStyle {
+    font_size: f32,
+    color: Color,
+    // Karaoke syllables before they are sung
+    secondary: Color,
+    outline_color: Color,
+    back_color: Color,
+    bold: bool,
+    italic: bool,
+    underline: bool,
+    outline: f32,
+    shadow: f32,
+    // Fractions of the font size, and degrees clockwise
+    scale: (f32, f32),
+    rotation: f32,
+    opaque_box: bool,
+    alignment: u8,
+    margins: (f32, f32, f32),
+}
+
+impl Default for ScriptStyle {
+    fn default() -> Self {
+        Self {
+            font_size: 18.0,
+            color: Color::WHITE,
+            secondary: Color::RED,
+            outline_color: Color::BLACK,
+            back_color: Color::BLACK,
+            bold: false,
+            italic: false,
+            underline: false,
+            outline: 2.0,
+            shadow: 2.0,
+            scale: (1.0, 1.0),
+            rotation: 0.0,
+            opaque_box: false,
+            alignment: 2,
+            margins: (10.0, 10.0, 10.0),
+        }
+    }
+}
+
+pub struct Script {
+    frame: (i32, i32),
+    section: Section,
+    legacy: bool,
+    play_res: (Option<f32>, Option<f32>),
+    styles: HashMap<String, ScriptStyle>,
+    style_format: Vec<String>,
+    event_format: Vec<String>,
+}
+
+impl Script {
+    pub fn new(frame: (i32, i32)) -> Self {
+        Self {
+            frame,
+            section: Section::Other,
+            legacy: false,
+            play_res: (None, None),
+            styles: HashMap::new(),
+            style_format: fields(
+                "Name, Fontname, Fontsize, PrimaryColour, SecondaryColour, OutlineColour, BackColour, Bold, Italic, Underline, StrikeOut, ScaleX, ScaleY, Spacing, Angle, BorderStyle, Outline, Shadow, Alignment, MarginL, MarginR, MarginV, Encoding",
+            ),
+            event_format: fields(
+                "Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text",
+            ),
+        }
+    }
+
+    // Track sections and header entries, returning whether the line is a dialogue event
+    pub fn read_line(&mut self, line: &str) -> bool {
+        let line = line.trim_start_matches('\u{feff}').trim();
+        if line.starts_with('[') && line.ends_with(']') {
+            self.section = match line.to_ascii_lowercase().as_str() {
+                "[script info]" => Section::ScriptInfo,
+                "[v4+ styles]" => Section::Styles,
+                "[v4 styles]" => {
+                    self.legacy = true;
+                    Section::Styles
+                }
+                "[events]" => Section::Events,
+                _ => Section::Other,
+            };
+            return false;
+        }
+
+        let Some((key, value)) = line.split_once(':') else {
+            return false;
+        };
+        let value = value.trim();
+        match (self.section, key) {
+            (Section::ScriptInfo, "PlayResX") => self.play_res.0 = value.parse().ok(),
+            (Section::ScriptInfo, "PlayResY") => self.play_res.1 = value.parse().ok(),
+            (Section::Styles, "Format") => self.style_format = fields(value),
+            (Section::Styles, "Style") => self.parse_style(value),
+            (Section::Events, "Format") => self.event_format = fields(value),
+            (Section::Events, "Dialogue") => return true,
+            _ => {}
+        }
+        false
+    }
+
+    fn parse_style(&mut self, value: &str) {
+        let values = split_fields(value, self.style_format.len());
+        let field = |name: &str| {
+            let index = self.style_format.iter().position(|f| f == name)?;
+            values.get(index).copied()
+        };
+        let number = |name: &str| field(name).and_then(|v| v.parse::<f32>().ok());
+        let color = |name: &str| field(name).and_then(parse_color);
+
+        let default = ScriptStyle::default();
+        let alignment = number("Alignment").map_or(default.alignment, |a| a as u8);
+        let style = ScriptStyle {
+            font_size: number("Fontsize").unwrap_or(default.font_size),
+            color: color("PrimaryColour").unwrap_or(default.color),
+            secondary: color("SecondaryColour").unwrap_or(default.secondary),
+            outline_color: color("OutlineColour").unwrap_or(default.outline_color),
+            back_color: color("BackColour").unwrap_or(default.back_color),
+            bold: number("Bold").is_some_and(|b| b != 0.0),
+            italic: number("Italic").is_some_and(|i| i != 0.0),
+            underline: number("Underline").is_some_and(|u| u != 0.0),
+            outline: number("Outline").unwrap_or(default.outline),
+            shadow: number("Shadow").unwrap_or(default.shadow),
+            scale: (
+                number("ScaleX").map_or(default.scale.0, |x| x / 100.0),
+                number("ScaleY").map_or(default.scale.1, |y| y / 100.0),
+            ),
+            rotation: number("Angle").map_or(default.rotation, |angle| -angle),
+            opaque_box: number("BorderStyle") == Some(3.0),
+            alignment: if self.legacy {
+                legacy_alignment(alignment)
+            } else {
+                alignment
+            },
+            margins: (
+                number("MarginL").unwrap_or(default.margins.0),
+                number("MarginR").unwrap_or(default.margins.1),
+                number("MarginV").unwrap_or(default.margins.2),
+            ),
+        };
+
+        let name = field("Name").unwrap_or("Default");
+        self.styles
+            .insert(name.trim_start_matches('*').to_string(), style);
+    }
+
+    // Script resolution, derived from the other axis or the SSA default when missing
+    fn play_res(&self) -> (f32, f32) {
+        match self.play_res {
+            (Some(x), Some(y)) => (x, y),
+            (Some(x), None) => (x, x * 3.0 / 4.0),
+            (None, Some(y)) => (y * 4.0 / 3.0, y),
+            (None, None) => (384.0, 288.0),
+        }
+    }
+
+    pub fn parse_dialogue(&self, line: &str) -> Option<Subtitle> {
+        let (_, value) = line.split_once(':')?;
+        let values = split_fields(value.trim_start(), self.event_format.len());
+        let field = |name: &str| {
+            let index = self.event_format.iter().position(|f| f == name)?;
+            values.get(index).copied()
+        };
+
+        let start = parse_timestamp(field("Start")?)?;
+        let end = parse_timestamp(field("End")?)?;
+        let text = field("Text")?;
+
+        let mut style = field("Style")
+            .and_then(|name| self.styles.get(name.trim_start_matches('*')))
+            .cloned()
+            .unwrap_or_default();
+        // Non-zero event margins take precedence over the style
+        let margin = |name: &str, fallback: f32| {
+            field(name)
+                .and_then(|m| m.parse::<f32>().ok())
+                .filter(|&m| m != 0.0)
+                .unwrap_or(fallback)
+        };
+        style.margins = (
+            margin("MarginL", style.margins.0),
+            margin("MarginR", style.margins.1),
+            margin("MarginV", style.margins.2),
+        );
+
+        // Text between override blocks takes the look in effect at that point
+        let mut overrides = Overrides {
+            duration: end.saturating_sub(start),
+            ..Default::default()
+        };
+        let mut lines = vec![Line::new()];
+        let mut push_text = |text: &str, style: &ScriptStyle, karaoke: Option<Karaoke>| {
+            let text = text.replace("\\n", " ").replace("\\h", "\u{a0}");
+            for (i, part) in text.split("\\N").enumerate() {
+                if i > 0 {
+                    lines.push(Line::new());
+                }
+                if !part.is_empty() {
+                    lines.last_mut().unwrap().push(Run {
+                        text: part.to_string(),
+                        bold: Some(style.bold),
+                        italic: Some(style.italic),
+                        underline: style.underline,
+                        color: Some(style.color),
+                        // `\2c` after `\k` still colours the syllable
+                        karaoke: karaoke.map(|karaoke| Karaoke {
+                            secondary: style.secondary,
+                            ..karaoke
+                        }),
+                        ..Default::default()
+                    });
+                }
+            }
+        };
+        let mut rest = text;
+        while let Some(open) = rest.find('{') {
+            if !overrides.drawing {
+                push_text(&rest[..open], &style, overrides.karaoke);
+            }
+            let Some(close) = rest[open..].find('}') else {
+                rest = &rest[open..];
+                break;
+            };
+            overrides.apply(&rest[open + 1..open + close], &mut style);
+            rest = &rest[open + close + 1..];
+        }
+        if !overrides.drawing {
+            push_text(rest, &style, overrides.karaoke);
+        }
+
+        Some(Subtitle {
+            start,
+            end,
+            layer: field("Layer")
+                .and_then(|layer| layer.parse().ok())
+                .unwrap_or(0),
+            lines,
+            placement: self.placement(&style, overrides.pos),
+            style: self.scale_style(&style, &overrides),
+            ..Default::default()
+        })
+    }
+
+    // Numpad alignment positioned against the margins or an explicit `\pos`
+    fn placement(&self, style: &ScriptStyle, pos: Option<(f32, f32)>) -> Placement {
+        let (res_x, res_y) = self.play_res();
+        let (margin_l, margin_r, margin_v) = style.margins;
+        let alignment = style.alignment.clamp(1, 9) - 1;
+        let horizontal = [Align::Start, Align::Center, Align::End][(alignment % 3) as usize];
+        let vertical = [Align::End, Align::Center, Align::Start][(alignment / 3) as usize];
+
+        let (x, y) = pos.unwrap_or_else(|| {
+            let x = match horizontal {
+                Align::Start => margin_l,
+                Align::Center => (margin_l + res_x - margin_r) / 2.0,
+                Align::End => res_x - margin_r,
+            };
+            let y = match vertical {
+                Align::Start => margin_v,
+                Align::Center => res_y / 2.0,
+                Align::End => res_y - margin_v,
+            };
+            (x, y)
+        });
+
+        Placement {
+            align: horizontal,
+            position: Some((x / res_x, horizontal)),
+            line: LinePosition::Fraction(y / res_y, vertical),
+            size: None,
+            clamp: false,
+        }
+    }
+
+    fn scale_style(&self, style: &ScriptStyle, overrides: &Overrides) -> Style {
+        let scale = self.frame.1 as f32 / self.play_res().1;
+        let shadow = style.shadow * scale;
+        Style {
+            font_size: Some(style.font_size * scale),
+            bold: style.bold,
+            italic: style.italic,
+            // Runs carry `\u` as it changes along the event
+            underline: false,
+            color: Some(style.color),
+            // An opaque box takes the outline colour in place of the outline
+            outline: Some(if style.opaque_box {
+                0.0
+            } else {
+                style.outline * scale
+            }),
+            outline_color: Some(style.outline_color),
+            shadow_offset: Some((shadow, shadow)),
+            // No shadow is drawn at zero depth, even when blurred
+            shadow_color: Some(if shadow > 0.0 {
+                style.back_color
+            } else {
+                Color::TRANSPARENT
+            }),
+            blur: overrides.blur.map(|blur| blur * scale),
+            background: style.opaque_box.then_some(style.outline_color),
+            fade: overrides.fade,
+            animation: Animation {
+                base: Transform {
+                    scale: style.scale,
+                    rotation: style.rotation,
+                    ..Default::default()
+                },
+                keyframes: overrides
+                    .keyframes
+                    .iter()
+                    .map(|keyframe| match keyframe.to {
+                        Property::Offset(x, y) => Keyframe {
+                            to: Property::Offset(
+                                x * self.frame.0 as f32 / self.play_res().0,
+                                y * scale,
+                            ),
+                            ..*keyframe
+                        },
+                        _ => *keyframe,
+                    })
+                    .collect(),
+            },
+        }
+    }
+}
+
+// Cue-wide state collected from override blocks, where the last value of each tag wins;
+// `\b`, `\i`, `\u`, `\c` and `\k` instead apply to the text that follows them
+#[derive(Default)]
+struct Overrides {
+    pos: Option<(f32, f32)>,
+    fade: Option<(u64, u64)>,
+    blur: Option<f32>,
+    drawing: bool,
+    // Syllable the text that follows is sung as, and when the next one starts
+    karaoke: Option<Karaoke>,
+    karaoke_time: u64,
+    // Event length, which `\move` and `\t` run over unless given times; moves are in script coordinates
+    duration: u64,
+    keyframes: Vec<Keyframe>,
+}
+
+impl Overrides {
+    fn apply(&mut self, block: &str, style: &mut ScriptStyle) {
+        for tag in split_tags(block) {
+            let number = |prefix: &str| tag[prefix.len()..].trim().parse::<f32>().ok();
+            if let Some(args) = tag.strip_prefix("pos") {
+                if let [x, y] = parse_args(args)[..] {
+                    self.pos = Some((x, y));
+                }
+            } else if let Some(args) = tag.strip_prefix("move") {
+                if let [x1, y1, x2, y2, ref times @ ..] = parse_args(args)[..] {
+                    self.pos = Some((x1, y1));
+                    let (start, end) = match *times {
+                        [t1, t2] if t2 > 0.0 => (t1 as u64, t2 as u64),
+                        _ => (0, self.duration),
+                    };
+                    self.keyframes.push(Keyframe {
+                        start,
+                        end,
+                        easing: Easing::Linear,
+                        to: Property::Offset(x2 - x1, y2 - y1),
+                    });
+                }
+            } else if let Some(args) = tag.strip_prefix('t') {
+                self.transition(args);
+            } else if let Some(property) = transform_tag(tag) {
+                match property {
+                    Property::ScaleX(x) => style.scale.0 = x,
+                    Property::ScaleY(y) => style.scale.1 = y,
+                    Property::Rotation(degrees) => style.rotation = degrees,
+                    Property::Offset(..) => {}
+                }
+            } else if let Some(args) = tag.strip_prefix("fad").filter(|a| !a.starts_with('e')) {
+                if let [fade_in, fade_out] = parse_args(args)[..] {
+                    self.fade = Some((fade_in as u64, fade_out as u64));
+                }
+            } else if tag.starts_with("bord") {
+                style.outline = number("bord").unwrap_or(style.outline);
+            } else if tag.starts_with("shad") {
+                style.shadow = number("shad").unwrap_or(style.shadow);
+            } else if tag.starts_with("blur") {
+                self.blur = number("blur");
+            } else if tag.starts_with("be") {
+                self.blur = number("be");
+            } else if tag.starts_with("an") {
+                if let Some(an) = number("an").filter(|an| (1.0..=9.0).contains(an)) {
+                    style.alignment = an as u8;
+                }
+            } else if tag.starts_with('a') && !tag.starts_with("alpha") {
+                // `\a` keeps the SSA numbering even in ASS scripts
+                if let Some(a) = number("a") {
+                    style.alignment = legacy_alignment(a as u8);
+                }
+            } else if tag.starts_with('b') {
+                // `\b1` or a font weight such as `\b700`
+                if let Some(b) = number("b") {
+                    style.bold = b == 1.0 || b >= 700.0;
+                }
+            } else if tag.starts_with('i') && !tag.starts_with("iclip") {
+                if let Some(i) = number("i") {
+                    style.italic = i != 0.0;
+                }
+            } else if tag.starts_with('u') {
+                if let Some(u) = number("u") {
+                    style.underline = u != 0.0;
+                }
+            } else if tag.starts_with("fs") {
+                if let Some(fs) = number("fs").filter(|fs| *fs > 0.0) {
+                    style.font_size = fs;
+                }
+            } else if let Some(value) = tag.strip_prefix("1c").or(tag.strip_prefix('c')) {
+                if let Some(color) = parse_color(value) {
+                    style.color = color.with_a(style.color.a());
+                }
+            } else if let Some(value) = tag.strip_prefix("2c") {
+                if let Some(color) = parse_color(value) {
+                    style.secondary = color.with_a(style.secondary.a());
+                }
+            } else if let Some(value) = tag.strip_prefix("3c") {
+                if let Some(color) = parse_color(value) {
+                    style.outline_color = color.with_a(style.outline_color.a());
+                }
+            } else if let Some(value) = tag.strip_prefix("4c") {
+                if let Some(color) = parse_color(value) {
+                    style.back_color = color.with_a(style.back_color.a());
+                }
+            } else if let Some(value) = tag.strip_prefix("kf").or(tag.strip_prefix('K')) {
+                self.sing(value, true, style);
+            } else if let Some(value) = tag.strip_prefix("ko").or(tag.strip_prefix('k')) {
+                // Outlines are not highlighted, so `\ko` switches the fill like `\k`
+                self.sing(value, false, style);
+            } else if tag.starts_with('p') {
+                // Vector drawings are not rendered, so their commands are dropped
+                self.drawing = number("p").is_some_and(|p| p > 0.0);
+            }
+        }
+    }
+
+    // `\t([t1, t2,] [accel,] tags)` animates the transform tags given towards their values
+    fn transition(&mut self, args: &str) {
+        let args = args.trim().trim_start_matches('(').trim_end_matches(')');
+        let Some(tags) = args.find('\\') else {
+            return;
+        };
+        let duration = self.duration as f32;
+        let (start, end, accel) = match parse_args(&args[..tags])[..] {
+            [t1, t2, accel] => (t1, t2, accel),
+            [t1, t2] => (t1, t2, 1.0),
+            [accel] => (0.0, duration, accel),
+            _ => (0.0, duration, 1.0),
+        };
+        let end = if end > 0.0 { end } else { duration };
+        let easing = if accel == 1.0 {
+            Easing::Linear
+        } else {
+            Easing::Power(accel)
+        };
+        for tag in split_tags(&args[tags..]) {
+            if let Some(to) = transform_tag(tag) {
+                self.keyframes.push(Keyframe {
+                    start: start.max(0.0) as u64,
+                    end: end as u64,
+                    easing,
+                    to,
+                });
+            }
+        }
+    }
+
+    // Start a syllable of the given centiseconds right after the previous one
+    fn sing(&mut self, value: &str, sweep: bool, style: &ScriptStyle) {
+        let Ok(centiseconds) = value.trim().parse::<f32>() else {
+            return;
+        };
+        let duration = (centiseconds.max(0.0) * 10.0) as u64;
+        self.karaoke = Some(Karaoke {
+            start: self.karaoke_time,
+            duration,
+            sweep,
+            secondary: style.secondary,
+        });
+        self.karaoke_time += duration;
+    }
+}
+
+// `\fscx`, `\fscy` and `\frz` (`\fr`), as fractions and degrees clockwise
+fn transform_tag(tag: &str) -> Option<Property> {
+    let number = |value: &str| value.trim().parse::<f32>().ok();
+    if let Some(value) = tag.strip_prefix("fscx") {
+        number(value).map(|x| Property::ScaleX(x / 100.0))
+    } else if let Some(value) = tag.strip_prefix("fscy") {
+        number(value).map(|y| Property::ScaleY(y / 100.0))
+    } else if let Some(value) = tag.strip_prefix("frz").or(tag.strip_prefix("fr")) {
+        number(value).map(|degrees| Property::Rotation(-degrees))
+    } else {
+        None
+    }
+}
+
+fn fields(format: &str) -> Vec<String> {
+    format.split(',').map(|f| f.trim().to_string()).collect()
+}
+
+// Split on commas, keeping any commas of the last field such as the event text
+fn split_fields(value: &str, count: usize) -> Vec<&str> {
+    value.splitn(count.max(1), ',').map(|v| v.trim()).collect()
+}
+
+// Split an override block into tags, keeping nested tags of `\t(...)` together
+fn split_tags(block: &str) -> Vec<&str> {
+    let mut tags = Vec::new();
+    let mut depth = 0;
+    let mut start = None;
+    for (i, c) in block.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            '\\' if depth == 0 => {
+                if let Some(start) = start {
+                    tags.push(block[start..i].trim());
+                }
+                start = Some(i + 1);
+            }
+            _ => {}
+        }
+    }
+    if let Some(start) = start {
+        tags.push(block[start..].trim());
+    }
+    tags
+}
+
+fn parse_args(args: &str) -> Vec<f32> {
+    args.trim()
+        .trim_start_matches('(')
+        .trim_end_matches(')')
+        .split(',')
+        .filter_map(|arg| arg.trim().parse().ok())
+        .collect()
+}
+
+// `&HAABBGGRR` where an alpha of 00 is opaque, or a decimal value of the same layout
+fn parse_color(value: &str) -> Option<Color> {
+    let value = value.trim().trim_end_matches('&');
+    let abgr = match value
+        .strip_prefix("&H")
+        .or_else(|| value.strip_prefix("&h"))
+    {
+        Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+        None => value.parse::<i64>().ok()? as u32,
+    };
+    let [r, g, b, a] = abgr.to_le_bytes();
+    Some(Color::from_argb(255 - a, r, g, b))
+}
+
+// SSA v4 alignment: 1-3 for subtitles, plus 4 for toptitles or 8 for midtitles
+fn legacy_alignment(alignment: u8) -> u8 {
+    let column = alignment & 3;
+    match alignment & 12 {
+        4 => column + 6,
+        8 => column + 3,
+        _ => column,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADER: &str = "[Script Info]
+PlayResX: 384
+PlayResY: 288
+
+[V4+ Styles]
+Format: Name, Fontname, Fontsize, PrimaryColour, SecondaryColour, OutlineColour, BackColour, Bold, Italic, Underline, StrikeOut, ScaleX, ScaleY, Spacing, Angle, BorderStyle, Outline, Shadow, Alignment, MarginL, MarginR, MarginV, Encoding
+Style: Default,Arial,20,&H00FFFFFF,&H000000FF,&H00000000,&H80000000,0,0,0,0,100,100,0,0,1,2,0,2,10,10,20,1
+
+[Events]
+Format: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text
+";
+
+    // Events of the header's script with the given lines, on a 1920x1080 frame
+    fn parse(events: &str) -> Vec<Subtitle> {
+        let mut script = Script::new((1920, 1080));
+        let mut subs = Vec::new();
+        for line in HEADER.lines().chain(events.lines()) {
+            if script.read_line(line) {
+                subs.push(script.parse_dialogue(line).unwrap());
+            }
+        }
+        subs
+    }
+
+    fn texts(sub: &Subtitle) -> Vec<&str> {
+        sub.lines
+            .iter()
+            .flatten()
+            .map(|run| run.text.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn parses_colors() {
+        // Bytes run alpha, blue, green, red, with alpha inverted
+        assert_eq!(
+            parse_color("&H00FF8040"),
+            Some(Color::from_argb(255, 0x40, 0x80, 0xFF))
+        );
+        assert_eq!(
+            parse_color("&H80000000&"),
+            Some(Color::from_argb(127, 0, 0, 0))
+        );
+        assert_eq!(parse_color("&hFF"), Some(Color::from_argb(255, 255, 0, 0)));
+        assert_eq!(parse_color("255"), Some(Color::from_argb(255, 255, 0, 0)));
+        assert_eq!(parse_color("lip(0,0,1,1)"), None);
+    }
+
+    #[test]
+    fn maps_legacy_alignment() {
+        assert_eq!(legacy_alignment(1), 1);
+        assert_eq!(legacy_alignment(3), 3);
+        // Toptitles
+        assert_eq!(legacy_alignment(5), 7);
+        assert_eq!(legacy_alignment(6), 8);
+        // Midtitles
+        assert_eq!(legacy_alignment(9), 4);
+        assert_eq!(legacy_alignment(11), 6);
+    }
+
+    #[test]
+    fn splits_nested_tags() {
+        assert_eq!(
+            split_tags("\\pos(1,2)\\t(0,100,\\frz90\\fscx50)\\b1"),
+            ["pos(1,2)", "t(0,100,\\frz90\\fscx50)", "b1"]
+        );
+        assert_eq!(split_tags("no tags"), Vec::<&str>::new());
+    }
+
+    #[test]
+    fn scales_to_the_frame() {
+        let subs = parse("Dialogue: 0,0:00:01.50,0:00:04.00,Default,,0,0,0,,Hello\\NWorld");
+        let sub = &subs[0];
+        assert_eq!((sub.start, sub.end), (1500, 4000));
+        assert_eq!(sub.lines.len(), 2);
+        // 288 script lines onto 1080 output lines
+        assert_eq!(sub.style.font_size, Some(75.0));
+        assert_eq!(sub.style.outline, Some(7.5));
+        assert_eq!(sub.style.shadow_color, Some(Color::TRANSPARENT));
+        // Bottom centre, MarginV above the bottom edge
+        assert!(sub.placement.align == Align::Center);
+        assert!(matches!(
+            sub.placement.line,
+            LinePosition::Fraction(y, Align::End) if y == 268.0 / 288.0
+        ));
+    }
+
+    #[test]
+    fn dispatches_tags_by_longest_name() {
+        let subs = parse(
+            "Dialogue: 0,0:00:00.00,0:00:05.00,Default,,0,0,0,,{\\bord4\\blur2\\b1\\pos(192,144)}A{\\be1\\fscx200\\fs30}B{\\clip(0,0,10,10)}C{\\c&H0000FF&\\p1}m 0 0 l 1 1{\\p0}D",
+        );
+        let sub = &subs[0];
+        assert_eq!(texts(sub), ["A", "B", "C", "D"]);
+        let runs: Vec<&Run> = sub.lines.iter().flatten().collect();
+
+        // `\bord` and `\blur` are not `\b`, but `\b1` is
+        assert_eq!(sub.style.outline, Some(15.0));
+        assert_eq!(runs[0].bold, Some(true));
+        // The last of `\blur` and `\be` wins
+        assert_eq!(sub.style.blur, Some(3.75));
+        // `\fscx` is not `\fs`
+        assert_eq!(sub.style.animation.base.scale, (2.0, 1.0));
+        assert_eq!(sub.style.font_size, Some(112.5));
+        // `\clip` is not `\c`
+        assert_eq!(runs[2].color, Some(Color::WHITE));
+        assert_eq!(runs[3].color, Some(Color::RED));
+        // `\pos` is not `\p`
+        assert!(matches!(
+            sub.placement.position,
+            Some((x, Align::Center)) if x == 0.5
+        ));
+        assert!(matches!(
+            sub.placement.line,
+            LinePosition::Fraction(y, Align::End) if y == 0.5
+        ));
+    }
+
+    #[test]
+    fn accumulates_karaoke_syllables() {
+        let subs = parse(
+            "Dialogue: 0,0:00:00.00,0:00:05.00,Default,,0,0,0,,{\\k10}Ka{\\kf20}ra{\\k0}{\\K5\\2c&H00FF00&}o",
+        );
+        let karaoke: Vec<Karaoke> = subs[0]
+            .lines
+            .iter()
+            .flatten()
+            .map(|run| run.karaoke.unwrap())
+            .collect();
+        let syllable = |start, duration, sweep, secondary| Karaoke {
+            start,
+            duration,
+            sweep,
+            secondary,
+        };
+        assert!(karaoke[0] == syllable(0, 100, false, Color::RED));
+        assert!(karaoke[1] == syllable(100, 200, true, Color::RED));
+        assert!(karaoke[2] == syllable(300, 50, true, Color::from_argb(255, 0, 255, 0)));
+    }
+
+    #[test]
+    fn reads_layers() {
+        let subs = parse(
+            "Dialogue: 1,0:00:00.00,0:00:02.00,Default,,0,0,0,,Text\nDialogue: 0,0:00:00.00,0:00:02.00,Default,,0,0,0,,{\\bord8}Glow",
+        );
+        assert_eq!((subs[0].layer, subs[1].layer), (1, 0));
+    }
+
+    #[test]
+    fn animates_moves_and_transitions() {
+        let subs = parse(
+            "Dialogue: 0,0:00:00.00,0:00:02.00,Default,,0,0,0,,{\\move(0,0,384,288,500,1500)\\t(\\frz90)}Sign",
+        );
+        let animation = &subs[0].style.animation;
+        assert!(matches!(
+            animation.keyframes[0],
+            Keyframe { start: 500, end: 1500, to: Property::Offset(x, y), .. } if x == 1920.0 && y == 1080.0
+        ));
+        // Counter-clockwise over the whole event
+        assert!(matches!(
+            animation.keyframes[1],
+            Keyframe { start: 0, end: 2000, to: Property::Rotation(r), .. } if r == -90.0
+        ));
+        assert_eq!(animation.at(1000).offset, (960.0, 540.0));
+        assert_eq!(animation.at(1000).rotation, -45.0);
+    }
+}
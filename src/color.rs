@@ -0,0 +1,208 @@
+use skia_safe::Color;
+
+// Parse `#RRGGBB`, `#RRGGBBAA`, `rgb()`, `rgba()` or a named color
+pub fn parse_color(value: &str) -> Option<Color> {
+    parse(value, parse_alpha)
+}
+
+// A colour setting, whose `rgba()` alpha is a fraction or percentage as in CSS
+pub fn parse_setting(key: &str, value: &str) -> Result<Color, String> {
+    parse(value, parse_css_alpha).ok_or_else(|| {
+        format!(
+            "Invalid {}: '{}' is not a #RRGGBB, #RRGGBBAA, rgb(), rgba() with alpha from 0 to 1 or a percentage, or named colour",
+            key, value
+        )
+    })
+}
+
+fn parse(value: &str, alpha: fn(&str) -> Option<u8>) -> Option<Color> {
+    let value = value.trim();
+
+    if let Some(hex) = value.strip_prefix('#') {
+        if !hex.is_ascii() {
+            return None;
+        }
+        let channel = |i: usize| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok();
+        return match hex.len() {
+            6 => Some(Color::from_argb(255, channel(0)?, channel(2)?, channel(4)?)),
+            8 => Some(Color::from_argb(
+                channel(6)?,
+                channel(0)?,
+                channel(2)?,
+                channel(4)?,
+            )),
+            _ => None,
+        };
+    }
+
+    let lower = value.to_ascii_lowercase();
+    if let Some(args) = lower
+        .strip_prefix("rgba(")
+        .or_else(|| lower.strip_prefix("rgb("))
+    {
+        let args: Vec<&str> = args.strip_suffix(')')?.split(',').map(str::trim).collect();
+        let channel = |arg: &str| arg.parse::<u8>().ok();
+        let (r, g, b) = (
+            channel(args[0])?,
+            channel(args.get(1)?)?,
+            channel(args.get(2)?)?,
+        );
+        let a = match (lower.starts_with("rgba("), args.len()) {
+            (false, 3) => 255,
+            (true, 4) => alpha(args[3])?,
+            _ => return None,
+        };
+        return Some(Color::from_argb(a, r, g, b));
+    }
+
+    named_color(&lower)
+}
+
+// Alpha as 0-255, as TTML takes it, or as a fraction when written with a decimal point or percent sign
+fn parse_alpha(arg: &str) -> Option<u8> {
+    if arg.contains(['.', '%']) {
+        return parse_css_alpha(arg);
+    }
+    arg.parse().ok()
+}
+
+// Alpha as a fraction from 0 to 1 or a percentage
+fn parse_css_alpha(arg: &str) -> Option<u8> {
+    let fraction: f32 = match arg.strip_suffix('%') {
+        Some(percent) => percent.parse::<f32>().ok()? / 100.0,
+        None => arg.parse().ok()?,
+    };
+    (0.0..=1.0)
+        .contains(&fraction)
+        .then(|| (fraction * 255.0).round() as u8)
+}
+
+fn named_color(name: &str) -> Option<Color> {
+    let (r, g, b) = match name {
+        "transparent" => return Some(Color::TRANSPARENT),
+        "black" => (0x00, 0x00, 0x00),
+        "silver" => (0xc0, 0xc0, 0xc0),
+        "gray" | "grey" => (0x80, 0x80, 0x80),
+        "white" => (0xff, 0xff, 0xff),
+        "maroon" => (0x80, 0x00, 0x00),
+        "red" => (0xff, 0x00, 0x00),
+        "purple" => (0x80, 0x00, 0x80),
+        "fuchsia" | "magenta" => (0xff, 0x00, 0xff),
+        "green" => (0x00, 0x80, 0x00),
+        "lime" => (0x00, 0xff, 0x00),
+        "olive" => (0x80, 0x80, 0x00),
+        "yellow" => (0xff, 0xff, 0x00),
+        "navy" => (0x00, 0x00, 0x80),
+        "blue" => (0x00, 0x00, 0xff),
+        "teal" => (0x00, 0x80, 0x80),
+        "aqua" | "cyan" => (0x00, 0xff, 0xff),
+        "orange" => (0xff, 0xa5, 0x00),
+        _ => return None,
+    };
+    Some(Color::from_argb(255, r, g, b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_hex() {
+        assert_eq!(
+            parse_color(" #FF8040 "),
+            Some(Color::from_argb(255, 0xFF, 0x80, 0x40))
+        );
+        assert_eq!(
+            parse_color("#ff804080"),
+            Some(Color::from_argb(0x80, 0xFF, 0x80, 0x40))
+        );
+        assert_eq!(parse_color("#FF804"), None);
+        assert_eq!(parse_color("#FF80400"), None);
+        assert_eq!(parse_color("#GG0000"), None);
+        // Six bytes, but not six hex digits
+        assert_eq!(parse_color("#ff8\u{e9}0"), None);
+    }
+
+    #[test]
+    fn parses_rgb_and_rgba() {
+        assert_eq!(
+            parse_color("rgb(255, 128, 0)"),
+            Some(Color::from_argb(255, 255, 128, 0))
+        );
+        assert_eq!(
+            parse_color("RGBA(255,128,0,64)"),
+            Some(Color::from_argb(64, 255, 128, 0))
+        );
+        // Each takes its own number of arguments
+        assert_eq!(parse_color("rgb(255,128,0,64)"), None);
+        assert_eq!(parse_color("rgba(255,128,0)"), None);
+        assert_eq!(parse_color("rgb(255,128)"), None);
+        assert_eq!(parse_color("rgb()"), None);
+        assert_eq!(parse_color("rgb(255,128,0"), None);
+        assert_eq!(parse_color("rgb(256,0,0)"), None);
+        assert_eq!(parse_color("rgb(-1,0,0)"), None);
+    }
+
+    #[test]
+    fn parses_alpha() {
+        assert_eq!(parse_alpha("0"), Some(0));
+        assert_eq!(parse_alpha("128"), Some(128));
+        assert_eq!(parse_alpha("255"), Some(255));
+        assert_eq!(parse_alpha("256"), None);
+        assert_eq!(parse_alpha("0.5"), Some(128));
+        assert_eq!(parse_alpha("1.0"), Some(255));
+        assert_eq!(parse_alpha("1.5"), None);
+        assert_eq!(parse_alpha("0%"), Some(0));
+        assert_eq!(parse_alpha("100%"), Some(255));
+        assert_eq!(parse_alpha("101%"), None);
+        assert_eq!(parse_alpha("-1%"), None);
+        assert_eq!(parse_alpha("half"), None);
+    }
+
+    #[test]
+    fn parses_named_colors() {
+        assert_eq!(
+            parse_color("Yellow"),
+            Some(Color::from_argb(255, 255, 255, 0))
+        );
+        assert_eq!(parse_color("transparent"), Some(Color::TRANSPARENT));
+        assert_eq!(parse_color("chartreuse"), None);
+        assert_eq!(parse_color(""), None);
+    }
+
+    #[test]
+    fn parses_settings_as_css() {
+        // Alpha is a fraction or percentage, so 1 is opaque
+        assert_eq!(
+            parse_setting("BOX_COLOR", "rgba(0,0,0,1)"),
+            Ok(Color::BLACK)
+        );
+        assert_eq!(
+            parse_setting("BOX_COLOR", "rgba(0,0,0,01)"),
+            Ok(Color::BLACK)
+        );
+        assert_eq!(
+            parse_setting("BOX_COLOR", "rgba(0,0,0,.5)"),
+            Ok(Color::from_argb(128, 0, 0, 0))
+        );
+        assert_eq!(
+            parse_setting("BOX_COLOR", "rgba(0,0,0,50%)"),
+            Ok(Color::from_argb(128, 0, 0, 0))
+        );
+        assert_eq!(
+            parse_setting("BOX_COLOR", "rgba(0,0,0,0)"),
+            Ok(Color::TRANSPARENT)
+        );
+        assert!(parse_setting("BOX_COLOR", "rgba(0,0,0,2)").is_err());
+        assert!(parse_setting("BOX_COLOR", "rgba(0,0,0,128)").is_err());
+        assert!(
+            parse_setting("TEXT_COLOR", "bright")
+                .is_err_and(|err| err.starts_with("Invalid TEXT_COLOR: 'bright'"))
+        );
+        // Markup and TTML alpha stays 0-255
+        assert_eq!(
+            parse_color("rgba(0,0,0,1)"),
+            Some(Color::from_argb(1, 0, 0, 0))
+        );
+    }
+}
@@ -0,0 +1,408 @@
+use crate::markup::{self, Dialect};
+use crate::subtitle::{Op, Subtitle};
+use crate::{ass, srt, ttml, vtt};
+use std::collections::VecDeque;
+use std::io::{self, BufRead, Chain, Cursor, Read};
+use std::str::FromStr;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Tsv,
+    Srt,
+    Vtt,
+    Ass,
+    Ttml,
+}
+
+impl FromStr for Format {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "tsv" => Ok(Format::Tsv),
+            "srt" => Ok(Format::Srt),
+            "vtt" | "webvtt" => Ok(Format::Vtt),
+            "ass" | "ssa" => Ok(Format::Ass),
+            "ttml" | "dfxp" | "imsc" => Ok(Format::Ttml),
+            _ => Err(()),
+        }
+    }
+}
+
+// Guess the input format from the first buffered bytes without consuming them
+pub fn detect(head: &[u8]) -> Format {
+    let head = String::from_utf8_lossy(head);
+    let head = head.trim_start_matches('\u{feff}').trim_start();
+    let first_line = head.lines().next().unwrap_or("").trim();
+
+    if first_line.starts_with("WEBVTT") {
+        return Format::Vtt;
+    }
+    if first_line.eq_ignore_ascii_case("[Script Info]") {
+        return Format::Ass;
+    }
+    if first_line.starts_with("<?xml") || first_line.starts_with("<tt") {
+        return Format::Ttml;
+    }
+
+    // A SubRip file opens with a bare cue index or directly with a timing line
+    if first_line.contains("-->")
+        || (!first_line.is_empty() && first_line.bytes().all(|b| b.is_ascii_digit()))
+    {
+        Format::Srt
+    } else {
+        Format::Tsv
+    }
+}
+
+// Lines of the input without their line endings; bytes that are not UTF-8, as in the
+// Windows-1252 files many subtitles come in, are replaced rather than ending the input
+struct LossyLines<R>(R);
+
+impl<R: BufRead> Iterator for LossyLines<R> {
+    type Item = String;
+
+    fn next(&mut self) -> Option<String> {
+        let mut line = Vec::new();
+        if self.0.read_until(b'\n', &mut line).ok()? == 0 {
+            return None;
+        }
+        if line.ends_with(b"\n") {
+            line.pop();
+            if line.ends_with(b"\r") {
+                line.pop();
+            }
+        }
+        Some(String::from_utf8_lossy(&line).into_owned())
+    }
+}
+
+pub struct Reader<R> {
+    lines: LossyLines<R>,
+    format: Format,
+    frame: (i32, i32),
+    script: ass::Script,
+    // Cues of a whole document parsed up front, in time order
+    document: Option<VecDeque<Result<Subtitle, String>>>,
+}
+
+impl<R: BufRead> Reader<R> {
+    pub fn new(input: R, format: Format, frame: (i32, i32)) -> Self {
+        Self {
+            lines: LossyLines(input),
+            format,
+            frame,
+            script: ass::Script::new(frame),
+            document: None,
+        }
+    }
+
+    fn next_block(&mut self) -> Option<Vec<String>> {
+        let mut block = Vec::new();
+        for line in self.lines.by_ref() {
+            let line = line.trim_start_matches('\u{feff}').trim_end();
+            if line.is_empty() {
+                if block.is_empty() {
+                    continue;
+                }
+                break;
+            }
+            block.push(line.to_string());
+        }
+        (!block.is_empty()).then_some(block)
+    }
+}
+
+impl<R: BufRead> Iterator for Reader<R> {
+    // Parsed subtitle, or the raw text that had to be skipped
+    type Item = Result<Subtitle, String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.format {
+            Format::Tsv => {
+                let line = self.lines.next()?;
+                Some(parse_line(&line).ok_or(line))
+            }
+            Format::Srt => {
+                let block = self.next_block()?;
+                Some(srt::parse_block(&block).ok_or_else(|| block.join("\n")))
+            }
+            Format::Vtt => loop {
+                let block = self.next_block()?;
+                if vtt::is_metadata(&block) {
+                    continue;
+                }
+                break Some(vtt::parse_block(&block).ok_or_else(|| block.join("\n")));
+            },
+            Format::Ass => {
+                // Events are often grouped by style or layer rather than by time, so the
+                // script is read whole and its events sorted
+                if self.document.is_none() {
+                    let mut events = Vec::new();
+                    for line in self.lines.by_ref() {
+                        if self.script.read_line(&line) {
+                            events.push(self.script.parse_dialogue(&line).ok_or(line));
+                        }
+                    }
+                    events.sort_by_key(|event| event.as_ref().map_or(0, |sub| sub.start));
+                    self.document = Some(events.into());
+                }
+                self.document.as_mut()?.pop_front()
+            }
+            Format::Ttml => {
+                if self.document.is_none() {
+                    let text: Vec<String> = self.lines.by_ref().collect();
+                    self.document =
+                        Some(match ttml::parse_document(&text.join("\n"), self.frame) {
+                            Ok(cues) => cues.into_iter().map(Ok).collect(),
+                            Err(err) => VecDeque::from([Err(err)]),
+                        });
+                }
+                self.document.as_mut()?.pop_front()
+            }
+        }
+    }
+}
+
+// Read input as the given format, or as the one its first line looks like; a pipe may
+// deliver that line in pieces, so it is read whole first and replayed
+fn open<R: BufRead>(
+    mut input: R,
+    format: Option<Format>,
+    frame: (i32, i32),
+) -> io::Result<Reader<Chain<Cursor<Vec<u8>>, R>>> {
+    let mut head = Vec::new();
+    if format.is_none() {
+        while input.read_until(b'\n', &mut head)? > 0 && head.trim_ascii().is_empty() {}
+    }
+    let format = format.unwrap_or_else(|| detect(&head));
+    Ok(Reader::new(Cursor::new(head).chain(input), format, frame))
+}
+
+pub enum Next {
+    Cue(Box<Subtitle>),
+    // Raw text that could not be parsed
+    Skipped(String),
+    // Nothing has arrived yet
+    Pending,
+    Done,
+}
+
+// Cues parsed ahead on their own thread, so parsing overlaps rendering and
+// a live source that has nothing to say never holds up a frame
+pub struct Source {
+    receiver: Receiver<Result<Subtitle, String>>,
+    // Wait for the next cue rather than report it pending
+    blocking: bool,
+}
+
+impl Source {
+    pub fn spawn(
+        format: Option<Format>,
+        frame: (i32, i32),
+        lookahead: usize,
+        blocking: bool,
+    ) -> Self {
+        let (sender, receiver) = mpsc::sync_channel(lookahead);
+        thread::spawn(move || {
+            let reader = match open(io::stdin().lock(), format, frame) {
+                Ok(reader) => reader,
+                Err(err) => {
+                    eprintln!("Failed to read input: {}", err);
+                    return;
+                }
+            };
+            for item in reader {
+                if sender.send(item).is_err() {
+                    break;
+                }
+            }
+        });
+        Self { receiver, blocking }
+    }
+
+    pub fn next(&mut self) -> Next {
+        let item = if self.blocking {
+            self.receiver.recv().ok()
+        } else {
+            match self.receiver.try_recv() {
+                Ok(item) => Some(item),
+                Err(TryRecvError::Empty) => return Next::Pending,
+                Err(TryRecvError::Disconnected) => None,
+            }
+        };
+        match item {
+            Some(Ok(sub)) => Next::Cue(Box::new(sub)),
+            Some(Err(text)) => Next::Skipped(text),
+            None => Next::Done,
+        }
+    }
+}
+
+fn parse_line(line: &str) -> Option<Subtitle> {
+    let mut parts: Vec<&str> = line.split('\t').collect();
+
+    // An operation and cue ID may lead the line
+    let op = match parts[0] {
+        "add" => Some(Op::Add),
+        "replace" => Some(Op::Replace),
+        "extend" => Some(Op::Extend),
+        "delete" => Some(Op::Delete),
+        _ => None,
+    };
+    let id = match op {
+        Some(_) => Some(parts.get(1)?.to_string()).filter(|id| !id.is_empty()),
+        None => None,
+    };
+    if op.is_some() {
+        parts.drain(..2);
+    }
+    let op = op.unwrap_or_default();
+
+    // Deletion needs only the time, and an extension no text
+    let start = parts.first()?.parse().ok()?;
+    let (end, text) = match op {
+        Op::Delete => (start, ""),
+        Op::Extend => (parts.get(1)?.parse().ok()?, ""),
+        Op::Add | Op::Replace => (parts.get(1)?.parse().ok()?, *parts.get(2)?),
+    };
+    if op != Op::Add && id.is_none() {
+        return None;
+    }
+
+    let lines = markup::parse_lines(text.split("   "), Dialect::Html);
+
+    Some(Subtitle {
+        start,
+        end,
+        id,
+        op,
+        lines,
+        ..Default::default()
+    })
+}
+
+// Parse `[HH:]MM:SS[,.]mmm` into milliseconds
+pub fn parse_timestamp(s: &str) -> Option<u64> {
+    let (clock, frac) = s.trim().split_once([',', '.'])?;
+    if frac.is_empty() || !frac.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    // Scale the fraction to milliseconds regardless of its digit count
+    let ms = format!("{:0<3}", &frac[..frac.len().min(3)])
+        .parse::<u64>()
+        .ok()?;
+
+    let mut parts = clock.split(':').rev();
+    let secs: u64 = parts.next()?.parse().ok()?;
+    let mins: u64 = parts.next()?.parse().ok()?;
+    let hours: u64 = match parts.next() {
+        Some(h) => h.parse().ok()?,
+        None => 0,
+    };
+    if parts.next().is_some() || secs >= 60 || mins >= 60 {
+        return None;
+    }
+
+    // Hours too many to count in milliseconds make the timestamp invalid
+    hours
+        .checked_mul(3_600_000)?
+        .checked_add((mins * 60 + secs) * 1000 + ms)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_cue_operations() {
+        let sub = parse_line("1000\t2000\tHello").unwrap();
+        assert!(sub.op == Op::Add && sub.id.is_none());
+        assert_eq!((sub.start, sub.end), (1000, 2000));
+        assert_eq!(sub.lines[0][0].text, "Hello");
+
+        let sub = parse_line("add\tc1\t1000\t2000\tHello").unwrap();
+        assert!(sub.op == Op::Add && sub.id.as_deref() == Some("c1"));
+        // Adding needs no ID
+        let sub = parse_line("add\t\t1000\t2000\tHello").unwrap();
+        assert!(sub.op == Op::Add && sub.id.is_none());
+
+        let sub = parse_line("replace\tc1\t1500\t3000\tHello there").unwrap();
+        assert!(sub.op == Op::Replace && sub.id.as_deref() == Some("c1"));
+        assert_eq!(sub.lines[0][0].text, "Hello there");
+
+        let sub = parse_line("extend\tc1\t2000\t4000").unwrap();
+        assert!(sub.op == Op::Extend && sub.lines.iter().flatten().all(|run| run.text.is_empty()));
+        assert_eq!((sub.start, sub.end), (2000, 4000));
+
+        let sub = parse_line("delete\tc1\t2500").unwrap();
+        assert!(sub.op == Op::Delete);
+        assert_eq!((sub.start, sub.end), (2500, 2500));
+
+        // Every operation but adding needs an ID
+        assert!(parse_line("replace\t\t1500\t3000\tHello").is_none());
+        assert!(parse_line("extend\t\t2000\t4000").is_none());
+        assert!(parse_line("delete\t\t2500").is_none());
+        assert!(parse_line("delete\tc1").is_none());
+        assert!(parse_line("replace\tc1\t1500\t3000").is_none());
+    }
+
+    #[test]
+    fn reads_on_past_bytes_that_are_not_utf8() {
+        let input: &[u8] =
+            b"1\n00:00:01,000 --> 00:00:02,000\nCaf\xe9\n\n2\r\n00:00:03,000 --> 00:00:04,000\r\nBye\r\n";
+        let subs: Vec<Subtitle> = Reader::new(input, Format::Srt, (1920, 1080))
+            .map(|item| item.unwrap())
+            .collect();
+        assert_eq!(subs.len(), 2);
+        assert_eq!(subs[0].lines[0][0].text, "Caf\u{fffd}");
+        assert_eq!(subs[1].start, 3000);
+        assert_eq!(subs[1].lines[0][0].text, "Bye");
+    }
+
+    #[test]
+    fn detects_formats_from_whole_lines() {
+        // The first read holds only the digits that start a TSV time
+        let input = (&b"12"[..]).chain(&b"00\t2000\tHello\n"[..]);
+        let mut reader = open(input, None, (1920, 1080)).unwrap();
+        assert!(reader.format == Format::Tsv);
+        assert_eq!(reader.next().unwrap().unwrap().start, 1200);
+
+        let input = (&b"\n\n1\n"[..]).chain(&b"00:00:01,000 --> 00:00:02,000\nHello\n"[..]);
+        let mut reader = open(input, None, (1920, 1080)).unwrap();
+        assert!(reader.format == Format::Srt);
+        assert_eq!(reader.next().unwrap().unwrap().start, 1000);
+    }
+
+    #[test]
+    fn detects_formats() {
+        assert!(detect(b"1\n00:00:01,000 --> 00:00:02,000\nHello\n") == Format::Srt);
+        assert!(detect(b"\xef\xbb\xbf1\r\n00:00:01,000 --> 00:00:02,000\r\n") == Format::Srt);
+        assert!(detect(b"\n\n00:00:01,000 --> 00:00:02,000\nHello\n") == Format::Srt);
+        assert!(detect(b"\xef\xbb\xbfWEBVTT\n\n") == Format::Vtt);
+        assert!(detect(b"[Script Info]\nScriptType: v4.00+\n") == Format::Ass);
+        assert!(detect(b"<?xml version=\"1.0\"?>\n<tt>") == Format::Ttml);
+        assert!(detect(b"1000\t2000\tHello\n") == Format::Tsv);
+        assert!(detect(b"add\tc1\t1000\t2000\tHello\n") == Format::Tsv);
+        assert!(detect(b"") == Format::Tsv);
+    }
+
+    #[test]
+    fn parses_timestamps() {
+        assert_eq!(parse_timestamp("00:00:01,000"), Some(1000));
+        assert_eq!(parse_timestamp("01:02:03.456"), Some(3_723_456));
+        assert_eq!(parse_timestamp(" 02:03.4 "), Some(123_400));
+        assert_eq!(parse_timestamp("00:00:01,5"), Some(1500));
+        assert_eq!(parse_timestamp("00:00:01,2345"), Some(1234));
+        assert_eq!(parse_timestamp("100:00:00,000"), Some(360_000_000));
+        assert_eq!(parse_timestamp("00:00:01"), None);
+        assert_eq!(parse_timestamp("00:00:01,"), None);
+        assert_eq!(parse_timestamp("00:00:60,000"), None);
+        assert_eq!(parse_timestamp("00:60:00,000"), None);
+        assert_eq!(parse_timestamp("1:00:00:00,000"), None);
+        assert_eq!(parse_timestamp("00:00:0a,000"), None);
+        assert_eq!(parse_timestamp("99999999999999999:00:00,000"), None);
+    }
+}
@@ -1,10 +1,28 @@
-use skia_safe::{
-    AlphaType, BlurStyle, Color, ColorType, Data, Font, FontMgr, ImageInfo, MaskFilter, Paint,
-    Point, Surface, surfaces,
-};
+use skia_safe::{AlphaType, Color, ColorType, FontMgr, ImageInfo, PaintJoin, surfaces};
 use std::env;
-use std::io::{self, BufRead, Write};
+use std::io::{self, Write};
 use std::str::FromStr;
+use std::thread;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+mod animation;
+mod ass;
+mod color;
+mod input;
+mod markup;
+mod render;
+mod roll_up;
+mod srt;
+mod subtitle;
+mod timing;
+mod ttml;
+mod vtt;
+
+use input::{Next, Source};
+use render::{BoxMode, Fonts, Wrap, draw_subtitle, line_count, line_height, wrap_lines};
+use roll_up::RollUp;
+use subtitle::{LinePosition, Op, Subtitle};
+use timing::{FrameRate, Retime, Rounding};
 
 fn env_or<T: FromStr>(key: &str, default: T) -> T {
     env::var(key)
@@ -13,40 +31,141 @@ fn env_or<T: FromStr>(key: &str, default: T) -> T {
         .unwrap_or(default)
 }
 
+// Unlike other settings, a colour that does not parse is an error rather than ignored
+fn env_color(key: &str, default: Color) -> Result<Color, String> {
+    match env::var(key) {
+        Ok(value) => color::parse_setting(key, &value),
+        Err(_) => Ok(default),
+    }
+}
+
+// A length in pixels, or as a percentage of `whole`
+fn env_length(key: &str, whole: f32) -> Option<f32> {
+    let value = env::var(key).ok()?;
+    match value.trim().strip_suffix('%') {
+        Some(percent) => percent
+            .parse::<f32>()
+            .ok()
+            .map(|percent| percent / 100.0 * whole),
+        None => value.trim().parse().ok(),
+    }
+}
+
 struct Config {
-    fps: u64,
+    fps: FrameRate,
+    retime: Retime,
+    start_rounding: Rounding,
+    end_rounding: Rounding,
+    min_cue_frames: u64,
+    frame_limit: Option<u64>,
+    exit_after_last_cue: bool,
     width: i32,
     height: i32,
     baseline: i32,
-    font_path: String,
+    font: String,
+    font_fallback: Vec<String>,
     font_size: f32,
     line_height_multiplier: f32,
+    text_color: Color,
+    outline_width: f32,
+    outline_color: Color,
+    outline_join: PaintJoin,
     shadow_angle: f32,
     shadow_distance: f32,
+    shadow_size: f32,
     shadow_blur: f32,
+    shadow_color: Color,
     shadow_opacity: f32,
-}
-
-struct Subtitle {
-    start: u64,
-    end: u64,
-    lines: Vec<String>,
+    max_width: f32,
+    wrap: Wrap,
+    box_color: Color,
+    box_mode: BoxMode,
+    box_padding: f32,
+    box_radius: f32,
+    fade_in_ms: u64,
+    fade_out_ms: u64,
+    input_format: Option<input::Format>,
+    input_lookahead: usize,
+    roll_up_rows: usize,
+    roll_up_frames: u64,
+    roll_up_timeout_ms: u64,
+    live: bool,
+    live_epoch_ms: Option<u64>,
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     // 1. Load Configuration
+    let width = env_or("WIDTH", 1920);
+    let fps = env_or("FPS", FrameRate::new(25, 1));
+    let frame_limit = timing::frame_limit(
+        fps,
+        env::var("FRAME_COUNT").ok().and_then(|v| v.parse().ok()),
+        env::var("DURATION_MS").ok().and_then(|v| v.parse().ok()),
+    );
     let config = Config {
-        fps: env_or("FPS", 25),
-        width: env_or("WIDTH", 1920),
+        fps,
+        retime: match env::var("SYNC") {
+            Ok(value) => value.parse().map_err(|_| {
+                format!(
+                    "Invalid SYNC: '{}' is not two from=to time pairs separated by ';'",
+                    value
+                )
+            })?,
+            Err(_) => Retime::new(env_or("OFFSET_MS", 0.0), env_or("TIME_SCALE", 1.0)),
+        },
+        start_rounding: env_or("START_ROUNDING", Rounding::Ceil),
+        end_rounding: env_or("END_ROUNDING", Rounding::Ceil),
+        min_cue_frames: env_or("MIN_CUE_FRAMES", 0),
+        frame_limit,
+        exit_after_last_cue: timing::exit_after_last_cue(
+            frame_limit,
+            env::var("EXIT_AFTER_LAST_CUE")
+                .ok()
+                .and_then(|v| v.parse().ok()),
+        ),
+        width,
         height: env_or("HEIGHT", 1080),
         baseline: env_or("BASELINE", 1026),
-        font_path: env::var("FONT_PATH").expect("FONT_PATH environment variable must be set"),
+        font: env::var("FONT_PATH")
+            .or_else(|_| env::var("FONT_FAMILY"))
+            .expect("FONT_PATH or FONT_FAMILY environment variable must be set"),
+        font_fallback: env::var("FONT_FALLBACK")
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|font| !font.is_empty())
+            .map(String::from)
+            .collect(),
         font_size: env_or("FONT_SIZE", 60.0),
         line_height_multiplier: env_or("LINE_HEIGHT", 1.0),
+        text_color: env_color("TEXT_COLOR", Color::WHITE)?,
+        outline_width: env_or("OUTLINE_WIDTH", 0.0),
+        outline_color: env_color("OUTLINE_COLOR", Color::BLACK)?,
+        outline_join: env::var("OUTLINE_JOIN")
+            .ok()
+            .and_then(|v| render::parse_join(&v))
+            .unwrap_or(PaintJoin::Round),
         shadow_angle: env_or("SHADOW_ANGLE", 45.0),
         shadow_distance: env_or("SHADOW_DISTANCE", 0.0),
+        shadow_size: env_or("SHADOW_SIZE", 0.0),
         shadow_blur: env_or("SHADOW_BLUR", 0.0),
+        shadow_color: env_color("SHADOW_COLOR", Color::BLACK)?,
         shadow_opacity: env_or("SHADOW_OPACITY", 1.0),
+        max_width: env_length("MAX_WIDTH", width as f32).unwrap_or(width as f32),
+        wrap: env_or("WRAP", Wrap::Greedy),
+        box_color: env_color("BOX_COLOR", Color::TRANSPARENT)?,
+        box_mode: env_or("BOX_MODE", BoxMode::Line),
+        box_padding: env_or("BOX_PADDING", 0.0),
+        box_radius: env_or("BOX_RADIUS", 0.0),
+        fade_in_ms: env_or("FADE_IN_MS", 0),
+        fade_out_ms: env_or("FADE_OUT_MS", 0),
+        input_format: env::var("INPUT_FORMAT").ok().and_then(|v| v.parse().ok()),
+        input_lookahead: env_or("INPUT_LOOKAHEAD", 256),
+        roll_up_rows: env_or("ROLL_UP", 0),
+        roll_up_frames: env_or("ROLL_UP_FRAMES", 8),
+        roll_up_timeout_ms: env_or("ROLL_UP_TIMEOUT_MS", 15000),
+        live: env_or("LIVE", false),
+        live_epoch_ms: env::var("LIVE_EPOCH_MS").ok().and_then(|v| v.parse().ok()),
     };
 
     // 2. Initialize Skia
@@ -59,103 +178,162 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     let mut surface = surfaces::raster(&info, None, None).expect("Failed to create skia surface");
 
-    // 3. Load Font
-    let font_data = Data::from_filename(&config.font_path).expect("Failed to read font file");
-    let font_mgr = FontMgr::new();
-    let typeface = font_mgr
-        .new_from_data(&font_data, None)
-        .expect("Failed to parse font");
-    let font = Font::new(typeface, config.font_size);
+    // 3. Load Fonts
+    let fonts = Fonts::new(
+        FontMgr::new(),
+        std::iter::once(&config.font)
+            .chain(&config.font_fallback)
+            .map(String::as_str),
+        config.font_size,
+    )?;
 
     // 4. Prepare IO
-    let stdin = io::stdin();
-    let mut line_iter = stdin.lock().lines();
+    let frame = (config.width, config.height);
+    let mut source = Source::spawn(
+        config.input_format,
+        frame,
+        config.input_lookahead,
+        !config.live,
+    );
     let mut stdout = io::stdout().lock();
 
+    // Live cue times count from the epoch rather than the first frame
+    let mut retime = config.retime;
+    let clock = Instant::now();
+    if config.live {
+        let now_ms = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as u64;
+        retime = live_retime(&retime, config.live_epoch_ms, now_ms);
+    }
+
     // 5. State Initialization
     let mut frame_count: u64 = 0;
-    let frame_dur_ms = 1000.0 / config.fps as f64;
 
-    let mut active_sub: Option<Subtitle> = None;
+    // Cues on screen in the order they appeared, and the next one read ahead
+    let mut active_subs: Vec<Subtitle> = Vec::new();
     let mut queued_sub: Option<Subtitle> = None;
+    let mut input_done = false;
+
+    // In roll-up mode every cue goes into the rows instead
+    let mut roll_up = (config.roll_up_rows > 0).then(|| {
+        RollUp::new(
+            config.roll_up_rows.clamp(2, 4),
+            config.fps.frame_ms(config.roll_up_frames),
+            config.roll_up_timeout_ms,
+        )
+    });
 
     // Rendering Cache
-    let mut last_rendered_key: Option<(u64, u64)> = None;
-    let mut is_cleared = false;
+    let mut last_rendered_key: Option<Vec<(u64, u64, u8, usize)>> = None;
 
     // Buffer for output
     let row_bytes = config.width as usize * 4;
     let mut pixel_buffer = vec![0u8; (config.height as usize) * row_bytes];
 
     loop {
-        let now_ms = (frame_count as f64 * frame_dur_ms) as u64;
+        if config.frame_limit.is_some_and(|limit| frame_count >= limit) {
+            break;
+        }
+        let now_ms = config.fps.frame_ms(frame_count);
 
         // --- Subtitle Management ---
-        if let Some(sub) = &active_sub {
-            if now_ms >= sub.end {
-                active_sub = None;
-            }
-        }
+        active_subs.retain(|sub| now_ms < sub.end);
 
-        if active_sub.is_none() {
-            if let Some(sub) = queued_sub.take() {
-                if now_ms < sub.end {
-                    if now_ms >= sub.start {
-                        active_sub = Some(sub);
-                    } else {
-                        queued_sub = Some(sub);
-                    }
+        // Activate due cues, reading ahead until one starts in the future
+        loop {
+            if let Some(sub) = &queued_sub {
+                if now_ms < sub.start {
+                    break;
                 }
-            }
-        }
-
-        if active_sub.is_none() && queued_sub.is_none() {
-            if let Some(line_res) = line_iter.next() {
-                match line_res {
-                    Ok(line) => {
-                        if let Some(sub) = parse_line(&line) {
-                            queued_sub = Some(sub);
-                            if let Some(qs) = &queued_sub {
-                                if now_ms >= qs.start && now_ms < qs.end {
-                                    active_sub = queued_sub.take();
-                                }
-                            }
-                        } else {
-                            eprintln!("Skipped: {}", line);
+                if let Some(mut sub) = queued_sub.take() {
+                    if let Some(roll_up) = &mut roll_up {
+                        // Rows roll up as they are drawn, so lines too wide are wrapped
+                        // into rows first, in the look the rows are drawn in
+                        let rows = Subtitle {
+                            lines: std::mem::take(&mut sub.lines),
+                            ..Default::default()
+                        };
+                        sub.lines = wrap_lines(&rows, &config, &fonts);
+                        roll_up.push(sub);
+                    } else {
+                        let line_count = |sub: &Subtitle| line_count(sub, &config, &fonts);
+                        if apply_cue(sub, &mut active_subs, now_ms, line_count) {
+                            // An updated cue may look different under the same key
+                            last_rendered_key = None;
                         }
                     }
-                    Err(_) => break,
                 }
-            } else {
+            }
+            if input_done {
                 break;
             }
-        } else if let Some(sub) = &queued_sub {
-            if active_sub.is_none() && now_ms >= sub.start && now_ms < sub.end {
-                active_sub = queued_sub.take();
+            match source.next() {
+                Next::Cue(mut sub) => {
+                    retime_cue(&mut sub, &retime);
+                    align_to_frames(&mut sub, &config);
+                    // Cues without a fade of their own take the configured one
+                    if sub.style.fade.is_none() && (config.fade_in_ms, config.fade_out_ms) != (0, 0)
+                    {
+                        sub.style.fade = Some((config.fade_in_ms, config.fade_out_ms));
+                    }
+                    queued_sub = Some(*sub);
+                }
+                Next::Skipped(text) => eprintln!("Skipped: {}", text),
+                Next::Pending => break,
+                Next::Done => input_done = true,
             }
         }
 
+        // Past the last cue, frames stay transparent until the frame limit
+        if config.exit_after_last_cue
+            && input_done
+            && active_subs.is_empty()
+            && queued_sub.is_none()
+            && roll_up
+                .as_ref()
+                .is_none_or(|roll_up| roll_up.is_finished(now_ms))
+        {
+            break;
+        }
+
         // --- Rendering ---
-        let mut needs_read = false;
-
-        if let Some(sub) = &active_sub {
-            let key = (sub.start, sub.end);
-            if last_rendered_key != Some(key) {
-                draw_subtitle(&mut surface, sub, &config, &font);
-                last_rendered_key = Some(key);
-                is_cleared = false;
-                needs_read = true;
-            } else if now_ms < sub.start && !is_cleared {
-                // Waiting for start time
-                surface.canvas().clear(Color::TRANSPARENT);
-                is_cleared = true;
-                needs_read = true;
-            }
-        } else if !is_cleared {
-            surface.canvas().clear(Color::TRANSPARENT);
+        // Fading, painted-on and karaoke cues change appearance between frames
+        let key: Vec<(u64, u64, u8, usize)> = active_subs
+            .iter()
+            .map(|sub| {
+                let alpha = (sub.opacity(now_ms) * 255.0) as u8;
+                (sub.start, sub.end, alpha, sub.stage(now_ms))
+            })
+            .collect();
+        // A sweeping highlight moves on every frame without the key changing
+        if active_subs.iter().any(|sub| sub.animating(now_ms)) {
+            last_rendered_key = None;
+        }
+        if let Some(roll_up) = &mut roll_up
+            && roll_up.update(now_ms)
+        {
             last_rendered_key = None;
-            is_cleared = true;
-            needs_read = true;
+        }
+        let needs_read = last_rendered_key.as_ref() != Some(&key);
+
+        if needs_read {
+            let canvas = surface.canvas();
+            canvas.clear(Color::TRANSPARENT);
+            // Lower layers first, and cues on one layer in the order they appeared
+            let mut layers: Vec<_> = active_subs.iter().zip(&key).collect();
+            layers.sort_by_key(|(sub, _)| sub.layer);
+            for (sub, &(_, _, alpha, _)) in layers {
+                draw_subtitle(canvas, sub, &config, &fonts, now_ms, alpha as f32 / 255.0);
+            }
+            if let Some(roll_up) = roll_up.as_ref().filter(|roll_up| !roll_up.is_empty()) {
+                // Rows start a line lower per row pushed up and scroll into place
+                let sub = roll_up.subtitle();
+                let scroll = roll_up.scroll(now_ms) * line_height(&sub, &config, &fonts);
+                canvas.save();
+                canvas.translate((0.0, scroll));
+                draw_subtitle(canvas, &sub, &config, &fonts, now_ms, 1.0);
+                canvas.restore();
+            }
+            last_rendered_key = Some(key);
         }
 
         // --- Output ---
@@ -164,6 +342,14 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             let _ = surface.read_pixels(&info, &mut pixel_buffer, row_bytes, (0, 0));
         }
 
+        // Live output goes out no sooner than the frame is due
+        if config.live
+            && let Some(wait) =
+                (clock + config.fps.frame_time(frame_count)).checked_duration_since(Instant::now())
+        {
+            thread::sleep(wait);
+        }
+
         if stdout.write_all(&pixel_buffer).is_err() {
             break;
         }
@@ -176,65 +362,284 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
-fn parse_line(line: &str) -> Option<Subtitle> {
-    let parts: Vec<&str> = line.split('\t').collect();
-    if parts.len() < 3 {
-        return None;
+// Shift cue times by how far the epoch lies after the first frame, which is before it once
+// the epoch has passed
+fn live_retime(retime: &Retime, epoch_ms: Option<u64>, now_ms: u64) -> Retime {
+    let epoch_ms = epoch_ms.unwrap_or(now_ms);
+    retime.shifted(epoch_ms as f64 - now_ms as f64)
+}
+
+// Map cue times to output times, stretching times within the cue along with it
+fn retime_cue(sub: &mut Subtitle, retime: &Retime) {
+    sub.start = retime.apply(sub.start);
+    sub.end = retime.apply(sub.end);
+    if let Some((fade_in, fade_out)) = &mut sub.style.fade {
+        *fade_in = retime.stretch(*fade_in);
+        *fade_out = retime.stretch(*fade_out);
+    }
+    for keyframe in &mut sub.style.animation.keyframes {
+        keyframe.start = retime.stretch(keyframe.start);
+        keyframe.end = retime.stretch(keyframe.end);
+    }
+    for run in sub.lines.iter_mut().flatten() {
+        run.reveal = run.reveal.map(|reveal| retime.stretch(reveal));
+        if let Some(karaoke) = &mut run.karaoke {
+            // Stretch the syllable end too, so syllables stay back to back
+            let end = retime.stretch(karaoke.start + karaoke.duration);
+            karaoke.start = retime.stretch(karaoke.start);
+            karaoke.duration = end - karaoke.start;
+        }
     }
+}
 
-    let start = parts[0].parse().ok()?;
-    let end = parts[1].parse().ok()?;
-    let text = parts[2];
+// Move cue boundaries onto the start of the first frame shown and the first frame after
+fn align_to_frames(sub: &mut Subtitle, config: &Config) {
+    let fps = config.fps;
+    let start = fps.frame_at(sub.start, config.start_rounding);
+    sub.start = fps.frame_ms(start);
+    if sub.end != u64::MAX {
+        let end = fps
+            .frame_at(sub.end, config.end_rounding)
+            .max(start + config.min_cue_frames);
+        sub.end = fps.frame_ms(end);
+    }
+}
 
-    let lines = text.split("   ").map(|s| s.to_string()).collect();
+// Show a due cue, or apply it to the cue on screen with its ID, telling whether that cue changed
+fn apply_cue(
+    mut sub: Subtitle,
+    active_subs: &mut Vec<Subtitle>,
+    now_ms: u64,
+    line_count: impl Fn(&Subtitle) -> usize,
+) -> bool {
+    let target = sub.id.as_ref().and_then(|id| {
+        active_subs
+            .iter()
+            .position(|other| other.id.as_ref() == Some(id))
+    });
+    match (sub.op, target) {
+        (Op::Extend, Some(i)) => {
+            active_subs[i].end = sub.end;
+            true
+        }
+        (Op::Delete, Some(i)) => {
+            active_subs.remove(i);
+            true
+        }
+        // Adding a cue under an ID already shown replaces it, so the ID stays with one cue
+        (Op::Add | Op::Replace, Some(i)) => {
+            // The replacement keeps its place in drawing order, and its row if that is still free
+            let replaced = active_subs.remove(i);
+            let row = match replaced.placement.line {
+                LinePosition::Number(n) if n < 0 => Some(-n - 1),
+                _ => None,
+            };
+            // It is already faded in, so it does not blink out and back in on each update
+            if let Some((fade_in, _)) = &mut sub.style.fade {
+                *fade_in = 0;
+            }
+            if now_ms < sub.end {
+                stack_subtitle(&mut sub, active_subs, row, line_count);
+                active_subs.insert(i, sub);
+            }
+            true
+        }
+        (Op::Extend | Op::Delete, None) => false,
+        // Replacing a cue no longer shown adds it anew
+        (Op::Add | Op::Replace, None) => {
+            if now_ms < sub.end {
+                stack_subtitle(&mut sub, active_subs, None, line_count);
+                active_subs.push(sub);
+            }
+            false
+        }
+    }
+}
 
-    Some(Subtitle { start, end, lines })
+// Raise a cue meant for the baseline above the rows taken by cues already shown there,
+// unless the row it is offered is free
+fn stack_subtitle(
+    sub: &mut Subtitle,
+    active_subs: &[Subtitle],
+    offered_row: Option<i32>,
+    line_count: impl Fn(&Subtitle) -> usize,
+) {
+    if !matches!(sub.placement.line, LinePosition::Auto) {
+        return;
+    }
+
+    // Rows counted up from the baseline, as [bottom, top)
+    let rows = |sub: &Subtitle| line_count(sub).max(1) as i32;
+    let taken: Vec<(i32, i32)> = active_subs
+        .iter()
+        .filter_map(|other| match other.placement.line {
+            LinePosition::Number(n) if n < 0 => Some((-n - 1, -n - 1 + rows(other))),
+            _ => None,
+        })
+        .collect();
+
+    let height = rows(sub);
+    let collision = |row: i32| {
+        taken
+            .iter()
+            .find(|&&(bottom, top)| row < top && bottom < row + height)
+    };
+    let mut row = offered_row
+        .filter(|&row| collision(row).is_none())
+        .unwrap_or(0);
+    while let Some(&(_, top)) = collision(row) {
+        row = top;
+    }
+    sub.placement.line = LinePosition::Number(-row - 1);
 }
 
-fn draw_subtitle(surface: &mut Surface, sub: &Subtitle, config: &Config, font: &Font) {
-    let canvas = surface.canvas();
-    canvas.clear(Color::TRANSPARENT);
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cue(id: &str, op: Op, end: u64, text: &str) -> Subtitle {
+        Subtitle {
+            id: Some(id.to_string()),
+            op,
+            ..Subtitle::from_text(0, end, text)
+        }
+    }
+
+    fn apply(sub: Subtitle, active_subs: &mut Vec<Subtitle>, now_ms: u64) -> bool {
+        apply_cue(sub, active_subs, now_ms, |sub| sub.lines.len())
+    }
+
+    fn row(sub: &Subtitle) -> i32 {
+        match sub.placement.line {
+            LinePosition::Number(n) => -n - 1,
+            _ => panic!("cue was not stacked"),
+        }
+    }
 
-    let line_height = font.spacing() * config.line_height_multiplier;
+    fn text(sub: &Subtitle) -> &str {
+        &sub.lines[0][0].text
+    }
 
-    // Shadow Setup
-    let mut shadow_paint = Paint::default();
-    shadow_paint.set_color(Color::from_argb(
-        (config.shadow_opacity * 255.0) as u8,
-        0,
-        0,
-        0,
-    ));
-    shadow_paint.set_anti_alias(true);
-    if config.shadow_blur > 0.0 {
-        // Convert radius to sigma
-        let sigma = config.shadow_blur / 2.0;
-        shadow_paint.set_mask_filter(MaskFilter::blur(BlurStyle::Normal, sigma, false));
+    #[test]
+    fn shifts_cue_times_to_the_live_epoch() {
+        let retime = Retime::new(0.0, 1.0);
+        // Started 3 s after the epoch, the cue at 5 s shows 2 s in
+        let late = live_retime(&retime, Some(10_000), 13_000);
+        assert_eq!(late.apply(5000), 2000);
+        // Started 3 s before the epoch, the cue at 0 waits for it
+        let early = live_retime(&retime, Some(10_000), 7000);
+        assert_eq!(early.apply(0), 3000);
+        // Without an epoch, cue times count from the first frame
+        assert_eq!(live_retime(&retime, None, 7000).apply(5000), 5000);
     }
 
-    // Text Setup
-    let mut text_paint = Paint::default();
-    text_paint.set_color(Color::WHITE);
-    text_paint.set_anti_alias(true);
+    #[test]
+    fn stacks_cues_above_each_other() {
+        let mut active_subs = Vec::new();
+        apply(cue("a", Op::Add, 5000, "one\ntwo"), &mut active_subs, 0);
+        apply(cue("b", Op::Add, 5000, "three"), &mut active_subs, 0);
+        assert_eq!(row(&active_subs[0]), 0);
+        assert_eq!(row(&active_subs[1]), 2);
+
+        // A free offered row is kept, and a taken one falls back to the lowest free row
+        let mut sub = cue("c", Op::Add, 5000, "four");
+        stack_subtitle(&mut sub, &active_subs, Some(4), |sub| sub.lines.len());
+        assert_eq!(row(&sub), 4);
+        let mut sub = cue("c", Op::Add, 5000, "four");
+        stack_subtitle(&mut sub, &active_subs, Some(1), |sub| sub.lines.len());
+        assert_eq!(row(&sub), 3);
+    }
 
-    // Shadow Offset
-    let rad = config.shadow_angle.to_radians();
-    let off_x = config.shadow_distance * rad.cos();
-    let off_y = config.shadow_distance * rad.sin();
+    #[test]
+    fn replace_keeps_its_row() {
+        let mut active_subs = Vec::new();
+        apply(cue("a", Op::Add, 5000, "one"), &mut active_subs, 0);
+        apply(cue("b", Op::Add, 5000, "two"), &mut active_subs, 0);
+        active_subs.remove(0);
+
+        let mut sub = cue("b", Op::Replace, 5000, "three");
+        sub.style.fade = Some((500, 500));
+        assert!(apply(sub, &mut active_subs, 1000));
+        assert_eq!(active_subs.len(), 1);
+        assert_eq!(text(&active_subs[0]), "three");
+        assert_eq!(row(&active_subs[0]), 1);
+        // Already faded in, so only the fade-out is left
+        assert!(active_subs[0].style.fade == Some((0, 500)));
+    }
 
-    for (i, line) in sub.lines.iter().enumerate() {
-        let line_index_from_bottom = (sub.lines.len() - 1 - i) as f32;
-        let y = config.baseline as f32 - (line_index_from_bottom * line_height);
+    #[test]
+    fn add_with_a_shown_id_replaces_it() {
+        let mut active_subs = Vec::new();
+        apply(cue("a", Op::Add, 5000, "one"), &mut active_subs, 0);
+        apply(cue("b", Op::Add, 5000, "two"), &mut active_subs, 0);
+
+        assert!(apply(
+            cue("a", Op::Add, 5000, "three"),
+            &mut active_subs,
+            1000
+        ));
+        assert_eq!(active_subs.len(), 2);
+        assert_eq!(text(&active_subs[0]), "three");
+        assert_eq!(row(&active_subs[0]), 0);
+    }
 
-        let width = font.measure_text(line, Some(&text_paint)).0;
-        let x = (config.width as f32 - width) / 2.0;
+    #[test]
+    fn ignores_unknown_ids() {
+        let mut active_subs = Vec::new();
+        apply(cue("a", Op::Add, 5000, "one"), &mut active_subs, 0);
+
+        assert!(!apply(
+            cue("b", Op::Extend, 9000, ""),
+            &mut active_subs,
+            1000
+        ));
+        assert!(!apply(
+            cue("b", Op::Delete, 1000, ""),
+            &mut active_subs,
+            1000
+        ));
+        assert_eq!(active_subs.len(), 1);
+        assert_eq!(active_subs[0].end, 5000);
+
+        // Replacing a cue no longer shown adds it anew
+        assert!(!apply(
+            cue("b", Op::Replace, 5000, "two"),
+            &mut active_subs,
+            1000
+        ));
+        assert_eq!(active_subs.len(), 2);
+    }
 
-        // Draw Shadow
-        if config.shadow_opacity > 0.0 {
-            canvas.draw_str(line, Point::new(x + off_x, y + off_y), font, &shadow_paint);
-        }
+    #[test]
+    fn extends_and_deletes_by_id() {
+        let mut active_subs = Vec::new();
+        apply(cue("a", Op::Add, 5000, "one"), &mut active_subs, 0);
+
+        assert!(apply(
+            cue("a", Op::Extend, 9000, ""),
+            &mut active_subs,
+            1000
+        ));
+        assert_eq!(active_subs[0].end, 9000);
+        assert_eq!(text(&active_subs[0]), "one");
+        assert!(apply(
+            cue("a", Op::Delete, 2000, ""),
+            &mut active_subs,
+            2000
+        ));
+        assert!(active_subs.is_empty());
+    }
 
-        // Draw Text
-        canvas.draw_str(line, Point::new(x, y), font, &text_paint);
+    #[test]
+    fn ended_replacement_removes_the_old_cue() {
+        let mut active_subs = Vec::new();
+        apply(cue("a", Op::Add, 5000, "one"), &mut active_subs, 0);
+
+        assert!(apply(
+            cue("a", Op::Replace, 2000, "two"),
+            &mut active_subs,
+            3000
+        ));
+        assert!(active_subs.is_empty());
     }
 }
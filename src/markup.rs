@@ -0,0 +1,254 @@
+use crate::color::parse_color;
+use crate::input::parse_timestamp;
+use crate::subtitle::{Line, Run};
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Dialect {
+    // `<b>`, `<i>`, `<u>` and `<font color>` as used in SubRip, and `{ms}` reveal times
+    // after the cue start; other tags are kept as text
+    Html,
+    // WebVTT cue spans with `<c.color>` classes, `<hh:mm:ss.mmm>` timestamps and
+    // entities; other tags are dropped
+    WebVtt,
+}
+
+// Parse the text lines of a cue into styled runs; tags may span several lines
+pub fn parse_lines<'a>(lines: impl IntoIterator<Item = &'a str>, dialect: Dialect) -> Vec<Line> {
+    // Open tags with the look they apply, and the time text from here on is revealed
+    let mut stack: Vec<(String, Run)> = Vec::new();
+    let mut reveal = None;
+
+    lines
+        .into_iter()
+        .map(|line| {
+            let mut runs = Line::new();
+            let mut rest = line;
+            while let Some(open) = rest.find('<') {
+                let Some(close) = rest[open..].find('>') else {
+                    break;
+                };
+                push_text(&mut runs, &stack, &mut reveal, &rest[..open], dialect);
+                let tag = &rest[open + 1..open + close];
+                if !apply_tag(tag, &mut stack, &mut reveal, dialect) {
+                    let text = &rest[open..=open + close];
+                    push_text(&mut runs, &stack, &mut reveal, text, dialect);
+                }
+                rest = &rest[open + close + 1..];
+            }
+            push_text(&mut runs, &stack, &mut reveal, rest, dialect);
+            runs
+        })
+        .collect()
+}
+
+fn push_text(
+    runs: &mut Line,
+    stack: &[(String, Run)],
+    reveal: &mut Option<u64>,
+    text: &str,
+    dialect: Dialect,
+) {
+    let mut text = text;
+    if dialect == Dialect::Html {
+        // A `{ms}` marker reveals the text after it that long after the cue starts
+        while let Some((before, time, after)) = split_reveal_marker(text) {
+            push_run(runs, stack, *reveal, before.to_string());
+            *reveal = Some(time);
+            text = after;
+        }
+    }
+    let text = match dialect {
+        Dialect::Html => text.to_string(),
+        Dialect::WebVtt => decode_entities(text),
+    };
+    push_run(runs, stack, *reveal, text);
+}
+
+fn push_run(runs: &mut Line, stack: &[(String, Run)], reveal: Option<u64>, text: String) {
+    if text.is_empty() {
+        return;
+    }
+    let mut look = stack.last().map(|(_, run)| run.clone()).unwrap_or_default();
+    look.reveal = reveal;
+    match runs.last_mut() {
+        Some(last) if last.same_look(&look) => last.text.push_str(&text),
+        _ => runs.push(Run { text, ..look }),
+    }
+}
+
+fn split_reveal_marker(text: &str) -> Option<(&str, u64, &str)> {
+    let mut from = 0;
+    while let Some(open) = text[from..].find('{').map(|open| from + open) {
+        let close = open + text[open..].find('}')?;
+        let digits = &text[open + 1..close];
+        if !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit()) {
+            return Some((&text[..open], digits.parse().ok()?, &text[close + 1..]));
+        }
+        from = open + 1;
+    }
+    None
+}
+
+// Returns whether the tag was recognised
+fn apply_tag(
+    tag: &str,
+    stack: &mut Vec<(String, Run)>,
+    reveal: &mut Option<u64>,
+    dialect: Dialect,
+) -> bool {
+    let tag = tag.trim();
+    if dialect == Dialect::WebVtt
+        && let Some(time) = parse_timestamp(tag)
+    {
+        *reveal = Some(time);
+        return true;
+    }
+    if let Some(name) = tag.strip_prefix('/') {
+        let name = name.trim().to_ascii_lowercase();
+        return match stack.iter().rposition(|(open, _)| *open == name) {
+            Some(index) => {
+                stack.truncate(index);
+                true
+            }
+            None => dialect == Dialect::WebVtt,
+        };
+    }
+
+    let (head, attributes) = tag.split_once(char::is_whitespace).unwrap_or((tag, ""));
+    let mut classes = head.split('.');
+    let name = classes.next().unwrap_or("").to_ascii_lowercase();
+    let mut run = stack.last().map(|(_, run)| run.clone()).unwrap_or_default();
+
+    match (dialect, name.as_str()) {
+        (_, "b") => run.bold = Some(true),
+        (_, "i") => run.italic = Some(true),
+        (_, "u") => run.underline = true,
+        (Dialect::Html, "font") => {
+            if let Some(color) = attribute(attributes, "color").and_then(parse_color) {
+                run.color = Some(color);
+            }
+        }
+        (Dialect::WebVtt, "c") => {
+            for class in classes {
+                match class.strip_prefix("bg_") {
+                    Some(background) => run.background = parse_color(background).or(run.background),
+                    None => run.color = parse_color(class).or(run.color),
+                }
+            }
+        }
+        // Voice, language and ruby tags carry no look of their own
+        (Dialect::WebVtt, _) => return true,
+        (Dialect::Html, _) => return false,
+    }
+
+    stack.push((name, run));
+    true
+}
+
+// Value of `name="value"`, `name='value'` or `name=value`
+fn attribute<'a>(attributes: &'a str, name: &str) -> Option<&'a str> {
+    let start = attributes
+        .to_ascii_lowercase()
+        .find(&format!("{}=", name))?
+        + name.len()
+        + 1;
+    let value = &attributes[start..];
+    match value.chars().next()? {
+        quote @ ('"' | '\'') => value[1..].split(quote).next(),
+        _ => value.split_whitespace().next(),
+    }
+}
+
+fn decode_entities(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&nbsp;", "\u{a0}")
+        .replace("&lrm;", "\u{200e}")
+        .replace("&rlm;", "\u{200f}")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use skia_safe::Color;
+
+    fn texts(lines: &[Line]) -> Vec<Vec<&str>> {
+        lines
+            .iter()
+            .map(|line| line.iter().map(|run| run.text.as_str()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn nests_tags_across_lines() {
+        let lines = parse_lines(
+            ["<b>one <i>two", "three</i> four", "five</b> six"],
+            Dialect::Html,
+        );
+        assert_eq!(
+            texts(&lines),
+            [
+                vec!["one ", "two"],
+                vec!["three", " four"],
+                vec!["five", " six"]
+            ]
+        );
+        assert!(lines[0][0].bold == Some(true) && lines[0][0].italic.is_none());
+        assert!(lines[0][1].bold == Some(true) && lines[0][1].italic == Some(true));
+        assert!(lines[1][0].italic == Some(true));
+        assert!(lines[1][1].bold == Some(true) && lines[1][1].italic.is_none());
+        assert!(lines[2][0].bold == Some(true));
+        assert!(lines[2][1].bold.is_none());
+    }
+
+    #[test]
+    fn parses_html_tags() {
+        let lines = parse_lines(
+            ["<font color=\"#ff0000\"><u>red</u></font> <x>plain</b>"],
+            Dialect::Html,
+        );
+        assert_eq!(texts(&lines), [vec!["red", " <x>plain</b>"]]);
+        assert!(
+            lines[0][0].color == Some(Color::from_argb(255, 255, 0, 0)) && lines[0][0].underline
+        );
+        assert!(lines[0][1].color.is_none() && !lines[0][1].underline);
+    }
+
+    #[test]
+    fn parses_webvtt_spans() {
+        let lines = parse_lines(
+            ["<v Bob><c.yellow.bg_blue>Hi</c> &lt;there&gt; &amp; <lang en>you</lang>"],
+            Dialect::WebVtt,
+        );
+        assert_eq!(texts(&lines), [vec!["Hi", " <there> & you"]]);
+        assert!(lines[0][0].color == Some(Color::from_argb(255, 255, 255, 0)));
+        assert!(lines[0][0].background == Some(Color::from_argb(255, 0, 0, 255)));
+        assert!(lines[0][1].color.is_none());
+    }
+
+    #[test]
+    fn extracts_reveal_markers() {
+        let lines = parse_lines(
+            ["one {500}two {x} <b>{1200}three</b>", "four"],
+            Dialect::Html,
+        );
+        assert_eq!(
+            texts(&lines),
+            [vec!["one ", "two {x} ", "three"], vec!["four"]]
+        );
+        let reveals: Vec<_> = lines.iter().flatten().map(|run| run.reveal).collect();
+        assert_eq!(reveals, [None, Some(500), Some(1200), Some(1200)]);
+        assert_eq!(split_reveal_marker("{}{12}"), Some(("{}", 12, "")));
+        assert_eq!(split_reveal_marker("{12"), None);
+
+        // WebVTT timestamps are absolute and left for the cue to make relative
+        let lines = parse_lines(["one <00:00:01.500>two <01:02.000>three"], Dialect::WebVtt);
+        assert_eq!(texts(&lines), [vec!["one ", "two ", "three"]]);
+        let reveals: Vec<_> = lines[0].iter().map(|run| run.reveal).collect();
+        assert_eq!(reveals, [None, Some(1500), Some(62_000)]);
+        // Braces are text in WebVTT
+        let lines = parse_lines(["one {500}two"], Dialect::WebVtt);
+        assert_eq!(texts(&lines), [vec!["one {500}two"]]);
+    }
+}
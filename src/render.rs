@@ -0,0 +1,663 @@
+use skia_safe::font_style::{Slant, Weight, Width};
+use skia_safe::textlayout::{
+    FontCollection, Paragraph, ParagraphBuilder, ParagraphStyle, RectHeightStyle, RectWidthStyle,
+    TextAlign, TextDecoration, TextDirection, TextStyle, TypefaceFontProvider,
+};
+use skia_safe::{
+    BlurStyle, Canvas, Color, Data, Font, FontMgr, FontStyle, MaskFilter, Paint, PaintJoin,
+    PaintStyle, RRect, Rect,
+};
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::Config;
+use crate::animation::Transform;
+use crate::subtitle::{Align, Line, LinePosition, Placement, Run, Style, Subtitle};
+
+// Typefaces available to the shaper, looked up by family name in order for each character
+pub struct Fonts {
+    collection: FontCollection,
+    families: Vec<String>,
+    // Line spacing of the primary typeface per unit of font size
+    spacing: f32,
+}
+
+impl Fonts {
+    // Each font is a file path, or a family name when no such file exists
+    pub fn new<'a>(
+        font_mgr: FontMgr,
+        fonts: impl IntoIterator<Item = &'a str>,
+        font_size: f32,
+    ) -> Result<Self, String> {
+        let mut provider = TypefaceFontProvider::new();
+        let mut families: Vec<String> = Vec::new();
+        let mut spacing = None;
+
+        for font in fonts {
+            // Files are registered under their own family name, so several
+            // files of one family provide its bold and italic faces
+            let typeface = if Path::new(font).is_file() {
+                let typeface = Data::from_filename(font)
+                    .and_then(|data| font_mgr.new_from_data(&data, None))
+                    .ok_or_else(|| format!("Failed to parse font file {}", font))?;
+                provider.register_typeface(typeface.clone(), Some(typeface.family_name().as_str()));
+                typeface
+            } else {
+                font_mgr
+                    .match_family_style(font, FontStyle::normal())
+                    .ok_or_else(|| format!("Font file or family {} not found", font))?
+            };
+
+            spacing.get_or_insert_with(|| {
+                Font::new(typeface.clone(), font_size).spacing() / font_size
+            });
+            let family = typeface.family_name();
+            if !families.contains(&family) {
+                families.push(family);
+            }
+        }
+
+        // Characters none of the fonts cover fall back to any installed font that has them
+        let mut collection = FontCollection::new();
+        collection.set_asset_font_manager(Some(provider.into()));
+        collection.set_default_font_manager(Some(font_mgr), None);
+
+        Ok(Self {
+            collection,
+            families,
+            spacing: spacing.ok_or("No font given")?,
+        })
+    }
+}
+
+pub fn parse_join(value: &str) -> Option<PaintJoin> {
+    match value.to_ascii_lowercase().as_str() {
+        "round" => Some(PaintJoin::Round),
+        "miter" | "mitre" => Some(PaintJoin::Miter),
+        "bevel" => Some(PaintJoin::Bevel),
+        _ => None,
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum BoxMode {
+    // A box behind each line
+    Line,
+    // One box around all lines of a cue
+    Block,
+}
+
+impl FromStr for BoxMode {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "line" => Ok(Self::Line),
+            "block" => Ok(Self::Block),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Wrap {
+    // Fill each line before breaking
+    Greedy,
+    // Even out line lengths, keeping the fewest lines
+    Balanced,
+}
+
+impl FromStr for Wrap {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "greedy" => Ok(Self::Greedy),
+            "balanced" => Ok(Self::Balanced),
+            _ => Err(()),
+        }
+    }
+}
+
+// Each pass paints the whole cue with its own paint over the same shaped layout
+#[derive(Clone, Copy)]
+enum Pass {
+    Background,
+    Shadow,
+    Outline,
+    Fill,
+    // Fill with karaoke syllables fully highlighted, drawn over the fill where the highlight has swept
+    Highlight,
+}
+
+#[derive(Default)]
+struct Paints {
+    shadow: Paint,
+    outline: Paint,
+    text: Paint,
+    background: Paint,
+}
+
+pub fn draw_subtitle(
+    canvas: &Canvas,
+    sub: &Subtitle,
+    config: &Config,
+    fonts: &Fonts,
+    now_ms: u64,
+    opacity: f32,
+) {
+    if opacity <= 0.0 {
+        return;
+    }
+    // Composite the whole cue at once so overlapping passes fade together
+    if opacity < 1.0 {
+        canvas.save_layer_alpha(None, (opacity * 255.0) as u32);
+    }
+
+    let style = &sub.style;
+    let outline = style.outline.unwrap_or(config.outline_width);
+    let edge_blur = style
+        .blur
+        .filter(|blur| *blur > 0.0)
+        .and_then(|blur| MaskFilter::blur(BlurStyle::Normal, blur / 2.0, false));
+
+    // Shadow Setup
+    let mut shadow_paint = Paint::default();
+    let shadow_alpha = config.shadow_color.a() as f32 * config.shadow_opacity;
+    shadow_paint.set_color(
+        style
+            .shadow_color
+            .unwrap_or(config.shadow_color.with_a(shadow_alpha as u8)),
+    );
+    shadow_paint.set_anti_alias(true);
+    let shadow_blur = style.blur.unwrap_or(config.shadow_blur);
+    if shadow_blur > 0.0 {
+        // Convert radius to sigma
+        let sigma = shadow_blur / 2.0;
+        shadow_paint.set_mask_filter(MaskFilter::blur(BlurStyle::Normal, sigma, false));
+    }
+    let shadow_spread = outline + config.shadow_size;
+    if shadow_spread > 0.0 {
+        // The shadow is cast by the outlined glyphs, grown by the spread
+        shadow_paint.set_style(PaintStyle::StrokeAndFill);
+        shadow_paint.set_stroke_width(shadow_spread * 2.0);
+        shadow_paint.set_stroke_join(config.outline_join);
+    }
+
+    // Outline Setup
+    // The stroke is centred on the glyph edge, and the fill covers its inner half
+    let mut outline_paint = Paint::default();
+    outline_paint.set_color(style.outline_color.unwrap_or(config.outline_color));
+    outline_paint.set_anti_alias(true);
+    outline_paint.set_style(PaintStyle::Stroke);
+    outline_paint.set_stroke_width(outline * 2.0);
+    outline_paint.set_stroke_join(config.outline_join);
+    outline_paint.set_mask_filter(edge_blur.clone());
+
+    // Text Setup
+    let mut text_paint = Paint::default();
+    text_paint.set_color(style.color.unwrap_or(config.text_color));
+    text_paint.set_anti_alias(true);
+    if outline <= 0.0 {
+        text_paint.set_mask_filter(edge_blur);
+    }
+
+    // Background Setup
+    let mut background_paint = Paint::default();
+    background_paint.set_color(style.background.unwrap_or(config.box_color));
+    background_paint.set_anti_alias(true);
+
+    let paints = Paints {
+        shadow: shadow_paint,
+        outline: outline_paint,
+        text: text_paint,
+        background: background_paint,
+    };
+
+    // Shadow Offset
+    let rad = config.shadow_angle.to_radians();
+    let (off_x, off_y) = style.shadow_offset.unwrap_or((
+        config.shadow_distance * rad.cos(),
+        config.shadow_distance * rad.sin(),
+    ));
+
+    // Cue Box Layout
+    let placement = &sub.placement;
+    let frame_width = config.width as f32;
+    let layout = |pass| {
+        let elapsed_ms = now_ms.saturating_sub(sub.start);
+        let mut paragraph = build_paragraph(sub, config, fonts, &paints, pass, elapsed_ms);
+        let (width, inset) = layout_paragraph(&mut paragraph, sub, config);
+        (paragraph, width, inset)
+    };
+
+    let (fill, box_width, inset) = layout(Pass::Fill);
+    let (box_left, anchor_x) = place_box(placement, box_width, frame_width);
+    let text_left = box_left + inset;
+
+    let line_metrics = fill.get_line_metrics();
+    let line_height = fill.height() / line_metrics.len().max(1) as f32;
+    let last_baseline = line_metrics.last().map_or(0.0, |line| line.baseline as f32);
+    let box_top = match placement.line {
+        LinePosition::Auto => config.baseline as f32 - last_baseline,
+        LinePosition::Number(n) if n < 0 => {
+            config.baseline as f32 - (-n - 1) as f32 * line_height - last_baseline
+        }
+        LinePosition::Number(n) => n as f32 * line_height,
+        LinePosition::Fraction(fraction, anchor) => {
+            fraction * config.height as f32 - fill.height() * anchor.factor()
+        }
+    };
+
+    // Animated Transform
+    // The cue moves, scales and rotates about the point it is placed at
+    let transform = style.animation.at(now_ms.saturating_sub(sub.start));
+    let transformed = transform != Transform::default();
+    if transformed {
+        let anchor_y = match placement.line {
+            LinePosition::Fraction(fraction, _) => fraction * config.height as f32,
+            _ => box_top + last_baseline,
+        };
+        canvas.save();
+        canvas.translate((anchor_x + transform.offset.0, anchor_y + transform.offset.1));
+        canvas.rotate(transform.rotation, None);
+        canvas.scale(transform.scale);
+        canvas.translate((-anchor_x, -anchor_y));
+    }
+
+    // Draw Background
+    // Boxes are filled opaque within a translucent layer, so padding reaching into the next line does not double up
+    if paints.background.alpha() > 0 {
+        let rects = line_metrics
+            .iter()
+            .filter(|line| line.width > 0.0)
+            .map(|line| {
+                let left = text_left + line.left as f32;
+                let baseline = box_top + line.baseline as f32;
+                Rect::new(
+                    left,
+                    baseline - line.ascent as f32,
+                    left + line.width as f32,
+                    baseline + line.descent as f32,
+                )
+            });
+        let rects: Vec<Rect> = match config.box_mode {
+            BoxMode::Line => rects.collect(),
+            BoxMode::Block => rects
+                .reduce(|mut block, rect| {
+                    block.join(rect);
+                    block
+                })
+                .into_iter()
+                .collect(),
+        };
+
+        let mut paint = paints.background.clone();
+        paint.set_alpha(255);
+        canvas.save_layer_alpha(None, paints.background.alpha() as u32);
+        for rect in rects {
+            let rect = rect.with_outset((config.box_padding, config.box_padding));
+            let rrect = RRect::new_rect_xy(rect, config.box_radius, config.box_radius);
+            canvas.draw_rrect(rrect, &paint);
+        }
+        canvas.restore();
+    }
+    if sub
+        .lines
+        .iter()
+        .flatten()
+        .any(|run| run.background.is_some())
+    {
+        layout(Pass::Background)
+            .0
+            .paint(canvas, (text_left, box_top));
+    }
+
+    // Draw Shadow
+    if paints.shadow.alpha() > 0 {
+        layout(Pass::Shadow)
+            .0
+            .paint(canvas, (text_left + off_x, box_top + off_y));
+    }
+
+    // Draw Outline
+    if outline > 0.0 {
+        layout(Pass::Outline).0.paint(canvas, (text_left, box_top));
+    }
+
+    // Draw Text
+    fill.paint(canvas, (text_left, box_top));
+
+    // Draw Karaoke Highlight
+    let sweeps = karaoke_sweeps(&fill, sub, now_ms.saturating_sub(sub.start));
+    if !sweeps.is_empty() {
+        let highlight = layout(Pass::Highlight).0;
+        for rect in sweeps {
+            canvas.save();
+            canvas.clip_rect(rect.with_offset((text_left, box_top)), None, true);
+            highlight.paint(canvas, (text_left, box_top));
+            canvas.restore();
+        }
+    }
+
+    if transformed {
+        canvas.restore();
+    }
+    if opacity < 1.0 {
+        canvas.restore();
+    }
+}
+
+// Distance between the baselines of the cue's lines
+pub fn line_height(sub: &Subtitle, config: &Config, fonts: &Fonts) -> f32 {
+    let font_size = sub.style.font_size.unwrap_or(config.font_size);
+    font_size * fonts.spacing * config.line_height_multiplier
+}
+
+// Rows the cue takes once its lines are wrapped
+pub fn line_count(sub: &Subtitle, config: &Config, fonts: &Fonts) -> usize {
+    let paints = Paints::default();
+    let mut paragraph = build_paragraph(sub, config, fonts, &paints, Pass::Fill, u64::MAX);
+    layout_paragraph(&mut paragraph, sub, config);
+    paragraph.line_number()
+}
+
+// The cue's lines broken into the rows they wrap to, each row holding its share of the runs
+pub fn wrap_lines(sub: &Subtitle, config: &Config, fonts: &Fonts) -> Vec<Line> {
+    let paints = Paints::default();
+    let mut paragraph = build_paragraph(sub, config, fonts, &paints, Pass::Fill, u64::MAX);
+    layout_paragraph(&mut paragraph, sub, config);
+    // Where each row starts, in UTF-16 units of the paragraph text, lines joined by `\n`
+    let breaks: Vec<usize> = paragraph
+        .get_line_metrics()
+        .iter()
+        .map(|line| line.start_index)
+        .collect();
+
+    let mut rows = Vec::new();
+    let mut offset = 0;
+    for (i, line) in sub.lines.iter().enumerate() {
+        if i > 0 {
+            offset += 1;
+        }
+        let line_start = offset;
+        rows.push(Line::new());
+        for run in line {
+            let mut text = String::new();
+            for c in run.text.chars() {
+                if offset != line_start && breaks.contains(&offset) {
+                    push_piece(&mut rows, run, &mut text);
+                    rows.push(Line::new());
+                }
+                text.push(c);
+                offset += c.len_utf16();
+            }
+            push_piece(&mut rows, run, &mut text);
+        }
+    }
+    rows
+}
+
+fn push_piece(rows: &mut [Line], run: &Run, text: &mut String) {
+    if let Some(row) = rows.last_mut()
+        && !text.is_empty()
+    {
+        row.push(Run {
+            text: std::mem::take(text),
+            ..run.clone()
+        });
+    }
+}
+
+// Break lines to fit the cue box and MAX_WIDTH, returning the box width and where the text
+// sits across it; a box not sized by the cue shrinks to the text
+fn layout_paragraph(paragraph: &mut Paragraph, sub: &Subtitle, config: &Config) -> (f32, f32) {
+    let box_width = sub.placement.size.map(|size| size * config.width as f32);
+    paragraph.layout(box_width.map_or(config.max_width, |width| width.min(config.max_width)));
+    let lines = paragraph.line_number();
+    if config.wrap == Wrap::Balanced && lines > 1 {
+        // Narrow the width as far as it goes without adding a line
+        let (mut low, mut high) = (0.0, paragraph.longest_line().ceil());
+        while high - low > 1.0 {
+            let mid = (low + high) / 2.0;
+            paragraph.layout(mid);
+            if paragraph.line_number() > lines {
+                low = mid;
+            } else {
+                high = mid;
+            }
+        }
+        paragraph.layout(high);
+    }
+
+    let width = paragraph.longest_line().ceil();
+    paragraph.layout(width);
+
+    // The lines keep their alignment within a wider box
+    let box_width = box_width.map_or(width, |box_width| box_width.max(width));
+    let align = match (sub.placement.align, direction(sub)) {
+        (Align::Start, TextDirection::RTL) => Align::End,
+        (Align::End, TextDirection::RTL) => Align::Start,
+        (align, _) => align,
+    };
+    (box_width, (box_width - width) * align.factor())
+}
+
+// Left edge of the cue box and the point across it the cue is placed at, which transforms
+// move it from; `\pos` may put the box partly off the frame
+fn place_box(placement: &Placement, box_width: f32, frame_width: f32) -> (f32, f32) {
+    let (position, position_align) = placement.position.unwrap_or((0.5, Align::Center));
+    let mut left = position * frame_width - box_width * position_align.factor();
+    if placement.clamp {
+        left = left.min(frame_width - box_width).max(0.0);
+    }
+    (left, left + box_width * position_align.factor())
+}
+
+// Shape the cue as one paragraph, one text style per run, so scripts and directions mix freely
+fn build_paragraph(
+    sub: &Subtitle,
+    config: &Config,
+    fonts: &Fonts,
+    paints: &Paints,
+    pass: Pass,
+    elapsed_ms: u64,
+) -> Paragraph {
+    let style = &sub.style;
+
+    let mut base = TextStyle::new();
+    base.set_font_families(&fonts.families);
+    base.set_font_size(style.font_size.unwrap_or(config.font_size));
+    base.set_height(fonts.spacing * config.line_height_multiplier);
+    base.set_height_override(true);
+
+    let mut paragraph_style = ParagraphStyle::new();
+    paragraph_style.set_text_align(match sub.placement.align {
+        Align::Start => TextAlign::Start,
+        Align::Center => TextAlign::Center,
+        Align::End => TextAlign::End,
+    });
+    paragraph_style.set_text_direction(direction(sub));
+    paragraph_style.set_text_style(&base);
+
+    let mut builder = ParagraphBuilder::new(&paragraph_style, &fonts.collection);
+    builder.push_style(&base);
+    for (i, line) in sub.lines.iter().enumerate() {
+        if i > 0 {
+            builder.add_text("\n");
+        }
+        for run in line {
+            let mut text_style = run_style(&base, run, style, paints, pass, elapsed_ms);
+            if !run.revealed(elapsed_ms) {
+                // Text still to be painted on keeps its place and width in the layout
+                let mut paint = paints.text.clone();
+                paint.set_color(Color::TRANSPARENT);
+                text_style.set_foreground_paint(&paint);
+                text_style.set_background_paint(&paint);
+                text_style.set_decoration_type(TextDecoration::NO_DECORATION);
+            }
+            builder.push_style(&text_style);
+            builder.add_text(&run.text);
+            builder.pop();
+        }
+    }
+    builder.build()
+}
+
+fn run_style(
+    base: &TextStyle,
+    run: &Run,
+    style: &Style,
+    paints: &Paints,
+    pass: Pass,
+    elapsed_ms: u64,
+) -> TextStyle {
+    let mut text_style = base.clone();
+
+    // Bold and italic are synthesised when the typeface has no such face
+    let bold = run.bold.unwrap_or(style.bold);
+    let italic = run.italic.unwrap_or(style.italic);
+    text_style.set_font_style(FontStyle::new(
+        if bold { Weight::BOLD } else { Weight::NORMAL },
+        Width::NORMAL,
+        if italic {
+            Slant::Italic
+        } else {
+            Slant::Upright
+        },
+    ));
+
+    match pass {
+        Pass::Background => {
+            let mut paint = paints.background.clone();
+            paint.set_color(Color::TRANSPARENT);
+            text_style.set_foreground_paint(&paint);
+            if let Some(background) = run.background {
+                paint.set_color(background);
+                text_style.set_background_paint(&paint);
+            }
+        }
+        Pass::Shadow => {
+            text_style.set_foreground_paint(&paints.shadow);
+        }
+        Pass::Outline => {
+            text_style.set_foreground_paint(&paints.outline);
+        }
+        Pass::Fill | Pass::Highlight => {
+            let mut paint = paints.text.clone();
+            if let Some(color) = run.color {
+                paint.set_color(color);
+            }
+            // Syllables keep the secondary colour until fully highlighted
+            if let (Pass::Fill, Some(karaoke)) = (pass, run.karaoke)
+                && karaoke.progress(elapsed_ms) < 1.0
+            {
+                paint.set_color(karaoke.secondary);
+            }
+            text_style.set_foreground_paint(&paint);
+            if run.underline || style.underline {
+                text_style.set_decoration_type(TextDecoration::UNDERLINE);
+                text_style.set_decoration_color(paint.color());
+            }
+        }
+    }
+    text_style
+}
+
+// Parts of the laid out text that karaoke syllables being swept have highlighted so far
+fn karaoke_sweeps(paragraph: &Paragraph, sub: &Subtitle, elapsed_ms: u64) -> Vec<Rect> {
+    let mut rects = Vec::new();
+    // Ranges are counted in UTF-16 units of the paragraph text, lines joined by `\n`
+    let mut offset = 0;
+    for (i, line) in sub.lines.iter().enumerate() {
+        if i > 0 {
+            offset += 1;
+        }
+        for run in line {
+            let len = run.text.encode_utf16().count();
+            let progress = run
+                .karaoke
+                .map_or(1.0, |karaoke| karaoke.progress(elapsed_ms));
+            if progress > 0.0 && progress < 1.0 && run.revealed(elapsed_ms) {
+                let boxes = paragraph.get_rects_for_range(
+                    offset..offset + len,
+                    RectHeightStyle::Max,
+                    RectWidthStyle::Tight,
+                );
+                // A syllable wrapped over lines is swept one box after another, in reading order
+                let mut swept = progress * boxes.iter().map(|b| b.rect.width()).sum::<f32>();
+                for text_box in boxes {
+                    let mut rect = text_box.rect;
+                    let width = swept.min(rect.width());
+                    if width <= 0.0 {
+                        break;
+                    }
+                    swept -= width;
+                    match text_box.direct {
+                        TextDirection::LTR => rect.right = rect.left + width,
+                        TextDirection::RTL => rect.left = rect.right - width,
+                    }
+                    rects.push(rect);
+                }
+            }
+            offset += len;
+        }
+    }
+    rects
+}
+
+// The first strongly directional letter sets the paragraph direction
+fn direction(sub: &Subtitle) -> TextDirection {
+    let rtl = sub
+        .lines
+        .iter()
+        .flatten()
+        .flat_map(|run| run.text.chars())
+        .find(|c| c.is_alphabetic())
+        .is_some_and(|c| {
+            matches!(c as u32, 0x0590..=0x08FF | 0xFB1D..=0xFDFF | 0xFE70..=0xFEFF | 0x10800..=0x10FFF | 0x1E800..=0x1EFFF)
+        });
+    if rtl {
+        TextDirection::RTL
+    } else {
+        TextDirection::LTR
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ass::Script;
+
+    #[test]
+    fn moves_boxes_off_the_frame() {
+        let mut script = Script::new((1920, 1080));
+        let mut sub = None;
+        for line in [
+            "[Script Info]",
+            "PlayResX: 384",
+            "PlayResY: 288",
+            "[Events]",
+            "Format: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text",
+            "Dialogue: 0,0:00:00.00,0:00:02.00,Default,,0,0,0,,{\\an2\\move(0,100,384,100)}Sign",
+        ] {
+            if script.read_line(line) {
+                sub = script.parse_dialogue(line);
+            }
+        }
+        let sub = sub.unwrap();
+
+        // Half the box starts off the left edge and ends off the right one
+        let (left, anchor_x) = place_box(&sub.placement, 400.0, 1920.0);
+        assert_eq!((left, anchor_x), (-200.0, 0.0));
+        let offset = sub.style.animation.at(2000).offset;
+        assert_eq!(anchor_x + offset.0, 1920.0);
+
+        // WebVTT keeps the box within the frame
+        let placement = Placement {
+            clamp: true,
+            ..sub.placement
+        };
+        assert_eq!(place_box(&placement, 400.0, 1920.0), (0.0, 200.0));
+    }
+}
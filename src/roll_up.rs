@@ -0,0 +1,283 @@
+use crate::subtitle::{Line, Op, Subtitle};
+use std::collections::VecDeque;
+
+// Caption rows on the baseline that new lines push upwards, as in CEA-608 roll-up
+pub struct RollUp {
+    rows: usize,
+    scroll_ms: u64,
+    timeout_ms: u64,
+    // Lines shown, oldest first, with the time each appeared
+    lines: VecDeque<(u64, Line)>,
+    // When the last lines came in, and how many rows they pushed the others up by
+    scroll_start: u64,
+    scroll_rows: usize,
+    // ID and row count of the newest cue, for refining it, and when the last cue ends
+    newest_id: Option<String>,
+    newest_rows: usize,
+    last_end: u64,
+    changed: bool,
+    scrolling: bool,
+    // Runs painted on or highlighted when last drawn
+    stage: usize,
+}
+
+impl RollUp {
+    pub fn new(rows: usize, scroll_ms: u64, timeout_ms: u64) -> Self {
+        Self {
+            rows,
+            scroll_ms,
+            timeout_ms,
+            lines: VecDeque::new(),
+            scroll_start: 0,
+            scroll_rows: 0,
+            newest_id: None,
+            newest_rows: 0,
+            last_end: 0,
+            changed: false,
+            scrolling: false,
+            stage: 0,
+        }
+    }
+
+    // Rows stay up for their timeout, so `extend` and `delete` have no effect
+    pub fn push(&mut self, mut sub: Subtitle) {
+        if matches!(sub.op, Op::Extend | Op::Delete) {
+            return;
+        }
+        // Rows are drawn as one cue starting at 0, so reveal and karaoke times count from it
+        for run in sub.lines.iter_mut().flatten() {
+            run.reveal = run.reveal.map(|reveal| sub.start + reveal);
+            if let Some(karaoke) = &mut run.karaoke {
+                karaoke.start += sub.start;
+            }
+        }
+        let shown = self.lines.len();
+        let added = sub.lines.len().min(self.rows);
+
+        // Replacing the newest cue rewrites its rows in place, as live captions are refined,
+        // and only rows it grows by scroll in
+        let mut scrolled = added;
+        if sub.op == Op::Replace && sub.id.is_some() && sub.id == self.newest_id {
+            let replaced = self.newest_rows.min(shown);
+            self.lines.truncate(shown - replaced);
+            scrolled = added.saturating_sub(replaced);
+        }
+
+        for line in sub.lines {
+            if self.lines.len() == self.rows {
+                self.lines.pop_front();
+            }
+            self.lines.push_back((sub.start, line));
+        }
+        // The first row appears in place, later ones scroll in below the rows shown
+        if shown > 0 && scrolled > 0 {
+            self.scroll_start = sub.start;
+            self.scroll_rows = scrolled;
+        }
+        self.newest_id = sub.id;
+        self.newest_rows = added;
+        self.last_end = self.last_end.max(sub.end);
+        self.changed = true;
+    }
+
+    // Drop rows past the timeout, telling whether the rows have to be drawn again
+    pub fn update(&mut self, now_ms: u64) -> bool {
+        let shown = self.lines.len();
+        if self.timeout_ms > 0 {
+            self.lines
+                .retain(|&(start, _)| now_ms < start.saturating_add(self.timeout_ms));
+        }
+
+        // The frame after scrolling ends is drawn again to settle the rows in place
+        let scrolling = self.scroll(now_ms) > 0.0;
+        // Words painted on and highlights sweeping change the look between cues
+        let sub = self.subtitle();
+        let stage = sub.stage(now_ms);
+        let changed = self.changed
+            || shown != self.lines.len()
+            || scrolling
+            || self.scrolling
+            || stage != self.stage
+            || sub.animating(now_ms);
+        self.changed = false;
+        self.scrolling = scrolling;
+        self.stage = stage;
+        changed
+    }
+
+    // Rows still to move up, counting down to 0 over the scroll time
+    pub fn scroll(&self, now_ms: u64) -> f32 {
+        let elapsed = now_ms.saturating_sub(self.scroll_start);
+        if self.scroll_rows == 0 || elapsed >= self.scroll_ms {
+            return 0.0;
+        }
+        (1.0 - elapsed as f32 / self.scroll_ms as f32) * self.scroll_rows as f32
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty()
+    }
+
+    // Whether nothing is left to show once no more cues come, as rows without a timeout stay
+    // up until the last cue ends
+    pub fn is_finished(&self, now_ms: u64) -> bool {
+        self.is_empty() || now_ms >= self.last_end
+    }
+
+    // The rows as a single cue on the baseline
+    pub fn subtitle(&self) -> Subtitle {
+        Subtitle {
+            start: 0,
+            end: u64::MAX,
+            lines: self.lines.iter().map(|(_, line)| line.clone()).collect(),
+            ..Default::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with_id(mut sub: Subtitle, id: &str, op: Op) -> Subtitle {
+        sub.id = Some(id.to_string());
+        sub.op = op;
+        sub
+    }
+
+    fn texts(roll_up: &RollUp) -> Vec<String> {
+        roll_up
+            .subtitle()
+            .lines
+            .iter()
+            .map(|line| line.iter().map(|run| run.text.as_str()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn drops_the_oldest_rows() {
+        let mut roll_up = RollUp::new(2, 0, 0);
+        roll_up.push(Subtitle::from_text(0, 9000, "one"));
+        roll_up.push(Subtitle::from_text(1000, 9000, "two\nthree"));
+        assert_eq!(texts(&roll_up), ["two", "three"]);
+        roll_up.push(Subtitle::from_text(2000, 9000, "four"));
+        assert_eq!(texts(&roll_up), ["three", "four"]);
+    }
+
+    #[test]
+    fn replaces_the_newest_rows_in_place() {
+        let mut roll_up = RollUp::new(3, 100, 0);
+        roll_up.push(with_id(Subtitle::from_text(0, 9000, "one"), "a", Op::Add));
+        roll_up.push(with_id(
+            Subtitle::from_text(1000, 9000, "two"),
+            "b",
+            Op::Add,
+        ));
+        assert_eq!(roll_up.scroll(1000), 1.0);
+
+        // Only the row it grew by scrolls in
+        roll_up.push(with_id(
+            Subtitle::from_text(2000, 9000, "two\nthree"),
+            "b",
+            Op::Replace,
+        ));
+        assert_eq!(texts(&roll_up), ["one", "two", "three"]);
+        assert_eq!(roll_up.scroll(2000), 1.0);
+
+        // Rewriting as many rows scrolls none
+        roll_up.push(with_id(
+            Subtitle::from_text(3000, 9000, "2\n3"),
+            "b",
+            Op::Replace,
+        ));
+        assert_eq!(texts(&roll_up), ["one", "2", "3"]);
+        assert_eq!(roll_up.scroll(3000), 0.0);
+
+        // Only the newest cue is replaced, older IDs add rows
+        roll_up.push(with_id(
+            Subtitle::from_text(4000, 9000, "uno"),
+            "a",
+            Op::Replace,
+        ));
+        assert_eq!(texts(&roll_up), ["2", "3", "uno"]);
+    }
+
+    #[test]
+    fn ignores_extend_and_delete() {
+        let mut roll_up = RollUp::new(2, 0, 0);
+        roll_up.push(with_id(Subtitle::from_text(0, 1000, "one"), "a", Op::Add));
+        roll_up.update(0);
+
+        roll_up.push(with_id(Subtitle::from_text(500, 9000, ""), "a", Op::Extend));
+        roll_up.push(with_id(Subtitle::from_text(600, 9000, ""), "a", Op::Delete));
+        assert_eq!(texts(&roll_up), ["one"]);
+        assert!(!roll_up.update(700));
+        assert!(roll_up.is_finished(1000));
+    }
+
+    #[test]
+    fn scrolls_over_the_scroll_time() {
+        let mut roll_up = RollUp::new(2, 100, 0);
+        // The first row appears in place
+        roll_up.push(Subtitle::from_text(0, 9000, "one"));
+        assert_eq!(roll_up.scroll(0), 0.0);
+
+        roll_up.push(Subtitle::from_text(1000, 9000, "two"));
+        assert_eq!(roll_up.scroll(1000), 1.0);
+        assert_eq!(roll_up.scroll(1050), 0.5);
+        assert_eq!(roll_up.scroll(1100), 0.0);
+
+        let mut roll_up = RollUp::new(2, 0, 0);
+        roll_up.push(Subtitle::from_text(0, 9000, "one"));
+        roll_up.push(Subtitle::from_text(1000, 9000, "two"));
+        assert_eq!(roll_up.scroll(1000), 0.0);
+    }
+
+    #[test]
+    fn updates_as_rows_scroll_and_expire() {
+        let mut roll_up = RollUp::new(2, 100, 5000);
+        roll_up.push(Subtitle::from_text(0, 1000, "one"));
+        assert!(roll_up.update(0));
+        assert!(!roll_up.update(40));
+
+        roll_up.push(Subtitle::from_text(1000, 2000, "two"));
+        assert!(roll_up.update(1000));
+        assert!(roll_up.update(1050));
+        // The frame after scrolling ends settles the rows
+        assert!(roll_up.update(1100));
+        assert!(!roll_up.update(1140));
+
+        assert!(!roll_up.update(4999));
+        assert!(roll_up.update(5000));
+        assert_eq!(texts(&roll_up), ["two"]);
+        assert!(roll_up.update(6000));
+        assert!(roll_up.is_empty());
+        assert!(!roll_up.update(6040));
+    }
+
+    #[test]
+    fn finishes_once_the_last_cue_ends() {
+        let mut roll_up = RollUp::new(2, 0, 0);
+        assert!(roll_up.is_finished(0));
+        roll_up.push(Subtitle::from_text(0, 3000, "one"));
+        roll_up.push(Subtitle::from_text(500, 1000, "two"));
+        assert!(!roll_up.is_finished(2000));
+        assert!(roll_up.is_finished(3000));
+    }
+
+    #[test]
+    fn reveals_words_from_each_row_start() {
+        let mut roll_up = RollUp::new(2, 0, 0);
+        roll_up.push(Subtitle::from_text(0, 9000, "one"));
+        let mut sub = Subtitle::from_text(1000, 9000, "two");
+        sub.lines[0][0].reveal = Some(500);
+        roll_up.push(sub);
+        assert_eq!(roll_up.subtitle().lines[1][0].reveal, Some(1500));
+
+        // The row is drawn again once its word is painted on
+        assert!(roll_up.update(1000));
+        assert!(!roll_up.update(1400));
+        assert!(roll_up.update(1500));
+        assert!(!roll_up.update(1600));
+    }
+}
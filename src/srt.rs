@@ -0,0 +1,60 @@
+use crate::input::parse_timestamp;
+use crate::markup::{self, Dialect};
+use crate::subtitle::Subtitle;
+
+// Parse a SubRip cue block: an optional index, a timing line and the text lines
+pub fn parse_block(block: &[impl AsRef<str>]) -> Option<Subtitle> {
+    let mut rest = block.iter().map(|line| line.as_ref());
+    let mut timing = rest.next()?;
+    if !timing.contains("-->") {
+        timing.trim().parse::<u64>().ok()?;
+        timing = rest.next()?;
+    }
+
+    let (start, end) = timing.split_once("-->")?;
+    let start = parse_timestamp(start)?;
+    // Drop the legacy `X1:… Y2:…` coordinates that may follow the end time
+    let end = parse_timestamp(end.split_whitespace().next()?)?;
+
+    let lines = markup::parse_lines(rest, Dialect::Html);
+
+    Some(Subtitle {
+        start,
+        end,
+        lines,
+        ..Default::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_blocks() {
+        let sub = parse_block(&["1", "00:00:01,000 --> 00:00:02,500", "Hello", "world"]).unwrap();
+        assert_eq!((sub.start, sub.end), (1000, 2500));
+        assert_eq!(sub.lines.len(), 2);
+        assert_eq!(sub.lines[1][0].text, "world");
+
+        // The index may be left out
+        let sub = parse_block(&["00:00:01,000 --> 00:00:02,000", "Hello"]).unwrap();
+        assert_eq!((sub.start, sub.end), (1000, 2000));
+
+        assert!(parse_block(&["one", "00:00:01,000 --> 00:00:02,000", "Hello"]).is_none());
+        assert!(parse_block(&["1", "00:00:01,000", "Hello"]).is_none());
+        assert!(parse_block(&["1"]).is_none());
+    }
+
+    #[test]
+    fn drops_legacy_coordinates() {
+        let sub = parse_block(&[
+            "2",
+            "00:00:03,000 --> 00:00:04,000  X1:100 X2:600 Y1:50 Y2:80",
+            "Hello",
+        ])
+        .unwrap();
+        assert_eq!((sub.start, sub.end), (3000, 4000));
+        assert_eq!(sub.lines[0][0].text, "Hello");
+    }
+}
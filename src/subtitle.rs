@@ -0,0 +1,347 @@
+use crate::animation::Animation;
+use skia_safe::Color;
+
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+pub enum Align {
+    Start,
+    #[default]
+    Center,
+    End,
+}
+
+impl Align {
+    // Fraction of a box's extent that lies before its anchor point
+    pub fn factor(self) -> f32 {
+        match self {
+            Align::Start => 0.0,
+            Align::Center => 0.5,
+            Align::End => 1.0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+pub enum LinePosition {
+    // Last line sits on `BASELINE`
+    #[default]
+    Auto,
+    // Fraction of the height, and which edge of the cue box sits on it
+    Fraction(f32, Align),
+    // Line number counted from the top, or from the bottom if negative
+    Number(i32),
+}
+
+#[derive(Clone, Copy, Default)]
+pub struct Placement {
+    // Alignment of each line within the cue box
+    pub align: Align,
+    // Fraction of the width, and which edge of the cue box sits on it
+    pub position: Option<(f32, Align)>,
+    pub line: LinePosition,
+    // Cue box width as a fraction of the width, otherwise the widest line
+    pub size: Option<f32>,
+    // Keep the cue box within the frame, as WebVTT requires; elsewhere it may run off the edges
+    pub clamp: bool,
+}
+
+// Per-cue overrides of the configured look, in output pixels
+#[derive(Clone, Default)]
+pub struct Style {
+    pub font_size: Option<f32>,
+    pub bold: bool,
+    pub italic: bool,
+    // Underline every run, as well as runs underlined themselves
+    pub underline: bool,
+    pub color: Option<Color>,
+    pub outline: Option<f32>,
+    pub outline_color: Option<Color>,
+    pub shadow_offset: Option<(f32, f32)>,
+    pub shadow_color: Option<Color>,
+    pub blur: Option<f32>,
+    pub background: Option<Color>,
+    // Fade-in and fade-out durations in milliseconds
+    pub fade: Option<(u64, u64)>,
+    pub animation: Animation,
+}
+
+// Stretch of text sharing one look; unset fields follow the cue style
+#[derive(Clone, Default, PartialEq)]
+pub struct Run {
+    pub text: String,
+    pub bold: Option<bool>,
+    pub italic: Option<bool>,
+    pub underline: bool,
+    pub color: Option<Color>,
+    pub background: Option<Color>,
+    // Milliseconds after the cue start the run appears, for text painted on as it is spoken
+    pub reveal: Option<u64>,
+    pub karaoke: Option<Karaoke>,
+}
+
+impl Run {
+    pub fn same_look(&self, other: &Run) -> bool {
+        self.bold == other.bold
+            && self.italic == other.italic
+            && self.underline == other.underline
+            && self.color == other.color
+            && self.background == other.background
+            && self.reveal == other.reveal
+            && self.karaoke == other.karaoke
+    }
+
+    pub fn revealed(&self, elapsed_ms: u64) -> bool {
+        self.reveal.is_none_or(|reveal| reveal <= elapsed_ms)
+    }
+}
+
+// Timing of a sung syllable in milliseconds after the cue start, highlighted from
+// the secondary colour to the run colour
+#[derive(Clone, Copy, PartialEq)]
+pub struct Karaoke {
+    pub start: u64,
+    pub duration: u64,
+    // Sweep the highlight across the syllable over its duration, rather than all at once at its start
+    pub sweep: bool,
+    pub secondary: Color,
+}
+
+impl Karaoke {
+    // Fraction of the syllable highlighted so far
+    pub fn progress(&self, elapsed_ms: u64) -> f32 {
+        let sung = elapsed_ms.saturating_sub(self.start);
+        if elapsed_ms < self.start {
+            0.0
+        } else if !self.sweep || sung >= self.duration {
+            1.0
+        } else {
+            sung as f32 / self.duration as f32
+        }
+    }
+}
+
+pub type Line = Vec<Run>;
+
+// What a cue read from input does to the cues on screen once it starts
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+pub enum Op {
+    // Show as a new cue
+    #[default]
+    Add,
+    // Take the place of the cue with the same ID
+    Replace,
+    // Move the end of the cue with the same ID to this end
+    Extend,
+    // Remove the cue with the same ID
+    Delete,
+}
+
+#[derive(Default)]
+pub struct Subtitle {
+    pub start: u64,
+    pub end: u64,
+    pub id: Option<String>,
+    pub op: Op,
+    // Cues on higher layers are drawn over those on lower ones
+    pub layer: i32,
+    pub lines: Vec<Line>,
+    pub placement: Placement,
+    pub style: Style,
+}
+
+impl Subtitle {
+    // Runs painted on or fully highlighted and keyframes finished so far, which changes the look as each one completes
+    pub fn stage(&self, now_ms: u64) -> usize {
+        let elapsed_ms = now_ms.saturating_sub(self.start);
+        self.lines
+            .iter()
+            .flatten()
+            .map(|run| {
+                let revealed = run.reveal.is_some() && run.revealed(elapsed_ms);
+                let sung = run
+                    .karaoke
+                    .is_some_and(|karaoke| karaoke.progress(elapsed_ms) >= 1.0);
+                revealed as usize + sung as usize
+            })
+            .sum::<usize>()
+            + self.style.animation.finished(elapsed_ms)
+    }
+
+    // Whether the look changes every frame, as while a karaoke highlight sweeps or the cue moves
+    pub fn animating(&self, now_ms: u64) -> bool {
+        let elapsed_ms = now_ms.saturating_sub(self.start);
+        self.style.animation.animating(elapsed_ms)
+            || self.lines.iter().flatten().any(|run| {
+                run.karaoke.is_some_and(|karaoke| {
+                    karaoke.sweep
+                        && (karaoke.start..karaoke.start + karaoke.duration).contains(&elapsed_ms)
+                })
+            })
+    }
+
+    // Opacity of the whole cue at the given time
+    pub fn opacity(&self, now_ms: u64) -> f32 {
+        let Some((fade_in, fade_out)) = self.style.fade else {
+            return 1.0;
+        };
+        let since_start = now_ms.saturating_sub(self.start);
+        let until_end = self.end.saturating_sub(now_ms);
+        let fade_in = if since_start < fade_in {
+            since_start as f32 / fade_in as f32
+        } else {
+            1.0
+        };
+        let fade_out = if until_end < fade_out {
+            until_end as f32 / fade_out as f32
+        } else {
+            1.0
+        };
+        fade_in.min(fade_out)
+    }
+}
+
+#[cfg(test)]
+impl Subtitle {
+    // A cue of plain text, one run per line
+    pub fn from_text(start: u64, end: u64, text: &str) -> Self {
+        Self {
+            start,
+            end,
+            lines: text
+                .split('\n')
+                .map(|line| {
+                    vec![Run {
+                        text: line.to_string(),
+                        ..Default::default()
+                    }]
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fading(start: u64, end: u64, fade: Option<(u64, u64)>) -> Subtitle {
+        Subtitle {
+            start,
+            end,
+            style: Style {
+                fade,
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn fades_in_and_out() {
+        let sub = fading(1000, 3000, Some((500, 1000)));
+        assert_eq!(sub.opacity(1000), 0.0);
+        assert_eq!(sub.opacity(1250), 0.5);
+        assert_eq!(sub.opacity(2000), 1.0);
+        assert_eq!(sub.opacity(2500), 0.5);
+        assert_eq!(sub.opacity(2990), 0.01);
+        assert_eq!(sub.opacity(3000), 0.0);
+    }
+
+    #[test]
+    fn takes_the_lower_of_overlapping_fades() {
+        let sub = fading(0, 400, Some((500, 500)));
+        assert_eq!(sub.opacity(100), 0.2);
+        assert_eq!(sub.opacity(200), 0.4);
+        assert_eq!(sub.opacity(300), 0.2);
+    }
+
+    #[test]
+    fn shows_cues_without_a_fade_at_once() {
+        let sub = fading(1000, 3000, Some((0, 500)));
+        assert_eq!(sub.opacity(1000), 1.0);
+        assert_eq!(sub.opacity(2750), 0.5);
+        let sub = fading(1000, 3000, None);
+        assert_eq!(sub.opacity(1000), 1.0);
+        assert_eq!(sub.opacity(2999), 1.0);
+    }
+
+    fn syllable(start: u64, duration: u64, sweep: bool) -> Karaoke {
+        Karaoke {
+            start,
+            duration,
+            sweep,
+            secondary: Color::RED,
+        }
+    }
+
+    fn sung(start: u64, syllables: &[Karaoke]) -> Subtitle {
+        Subtitle {
+            start,
+            end: start + 5000,
+            lines: vec![
+                syllables
+                    .iter()
+                    .map(|&karaoke| Run {
+                        text: "la".to_string(),
+                        karaoke: Some(karaoke),
+                        ..Default::default()
+                    })
+                    .collect(),
+            ],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn highlights_syllables() {
+        // `\k` highlights all at once at its start
+        let k = syllable(100, 200, false);
+        assert_eq!(k.progress(99), 0.0);
+        assert_eq!(k.progress(100), 1.0);
+        // `\kf` sweeps across its duration
+        let kf = syllable(100, 200, true);
+        assert_eq!(kf.progress(100), 0.0);
+        assert_eq!(kf.progress(150), 0.25);
+        assert_eq!(kf.progress(200), 0.5);
+        assert_eq!(kf.progress(300), 1.0);
+        assert_eq!(kf.progress(400), 1.0);
+        // A syllable of no length is done at its start
+        let empty = syllable(100, 0, true);
+        assert_eq!(empty.progress(99), 0.0);
+        assert_eq!(empty.progress(100), 1.0);
+    }
+
+    #[test]
+    fn animates_only_while_a_highlight_sweeps() {
+        let sub = sung(
+            1000,
+            &[
+                syllable(0, 100, false),
+                syllable(100, 200, true),
+                syllable(300, 0, true),
+            ],
+        );
+        assert!(!sub.animating(1000));
+        assert!(!sub.animating(1099));
+        assert!(sub.animating(1100));
+        assert!(sub.animating(1299));
+        assert!(!sub.animating(1300));
+        assert!(!sub.animating(2000));
+    }
+
+    #[test]
+    fn stages_as_syllables_complete() {
+        let sub = sung(
+            1000,
+            &[
+                syllable(0, 100, false),
+                syllable(100, 200, true),
+                syllable(300, 0, true),
+            ],
+        );
+        assert_eq!(sub.stage(1000), 1);
+        assert_eq!(sub.stage(1200), 1);
+        assert_eq!(sub.stage(1299), 1);
+        assert_eq!(sub.stage(1300), 3);
+    }
+}
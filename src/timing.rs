@@ -0,0 +1,305 @@
+use crate::input::parse_timestamp;
+use std::str::FromStr;
+use std::time::Duration;
+
+// Frames per second as an exact fraction, such as 30000/1001 for NTSC
+#[derive(Clone, Copy)]
+pub struct FrameRate {
+    num: u64,
+    den: u64,
+}
+
+impl FrameRate {
+    pub fn new(num: u64, den: u64) -> Self {
+        let divisor = gcd(num, den);
+        Self {
+            num: num / divisor,
+            den: den / divisor,
+        }
+    }
+
+    // Start time of a frame, truncated to the millisecond
+    pub fn frame_ms(&self, frame: u64) -> u64 {
+        (frame as u128 * 1000 * self.den as u128 / self.num as u128) as u64
+    }
+
+    // Exact start time of a frame, for pacing output
+    pub fn frame_time(&self, frame: u64) -> Duration {
+        Duration::from_nanos(
+            (frame as u128 * 1_000_000_000 * self.den as u128 / self.num as u128) as u64,
+        )
+    }
+
+    // Frame a time falls on, rounding a time between frames as the policy says
+    pub fn frame_at(&self, ms: u64, rounding: Rounding) -> u64 {
+        let (n, d) = (ms as u128 * self.num as u128, 1000 * self.den as u128);
+        let frame = match rounding {
+            Rounding::Floor => n / d,
+            Rounding::Nearest => (2 * n + d) / (2 * d),
+            Rounding::Ceil => n.div_ceil(d),
+        };
+        frame as u64
+    }
+}
+
+// Which frame a cue boundary between two frames goes to
+#[derive(Clone, Copy)]
+pub enum Rounding {
+    Floor,
+    Nearest,
+    Ceil,
+}
+
+impl FromStr for Rounding {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "floor" => Ok(Self::Floor),
+            "nearest" => Ok(Self::Nearest),
+            "ceil" => Ok(Self::Ceil),
+            _ => Err(()),
+        }
+    }
+}
+
+impl FromStr for FrameRate {
+    type Err = ();
+
+    // `30000/1001`, `25` or `29.97`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (num, den) = match s.split_once('/') {
+            Some((num, den)) => (
+                num.trim().parse().map_err(|_| ())?,
+                den.trim().parse().map_err(|_| ())?,
+            ),
+            None => match s.split_once('.') {
+                None => (s.parse().map_err(|_| ())?, 1),
+                Some((whole, fraction)) => {
+                    let den = 10u64.checked_pow(fraction.len() as u32).ok_or(())?;
+                    let num = format!("{}{}", whole, fraction).parse().map_err(|_| ())?;
+                    ntsc(num, den).unwrap_or((num, den))
+                }
+            },
+        };
+        if num == 0 || den == 0 {
+            return Err(());
+        }
+        Ok(Self::new(num, den))
+    }
+}
+
+// Linear mapping of input times onto output times, for offset or drifting timings
+#[derive(Clone, Copy)]
+pub struct Retime {
+    scale: f64,
+    offset: f64,
+}
+
+impl Retime {
+    pub fn new(offset_ms: f64, scale: f64) -> Self {
+        Self {
+            scale,
+            offset: offset_ms,
+        }
+    }
+
+    // The same mapping with times moved by `ms` more
+    pub fn shifted(&self, ms: f64) -> Self {
+        Self::new(self.offset + ms, self.scale)
+    }
+
+    // Through two points, each an input time and the output time it should be at
+    fn through((from1, to1): (f64, f64), (from2, to2): (f64, f64)) -> Option<Self> {
+        if from1 == from2 {
+            return None;
+        }
+        let scale = (to2 - to1) / (from2 - from1);
+        Some(Self::new(to1 - from1 * scale, scale))
+    }
+
+    // Times moved before zero are clamped to it, and open ends stay open
+    pub fn apply(&self, ms: u64) -> u64 {
+        if ms == u64::MAX {
+            return ms;
+        }
+        (ms as f64 * self.scale + self.offset).round().max(0.0) as u64
+    }
+
+    // A length of time, such as from the cue start, which only the scale changes
+    pub fn stretch(&self, ms: u64) -> u64 {
+        if ms == u64::MAX {
+            return ms;
+        }
+        (ms as f64 * self.scale).round().max(0.0) as u64
+    }
+}
+
+impl FromStr for Retime {
+    type Err = ();
+
+    // `from=to;from=to`, with times in milliseconds or as timestamps
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let time = |value: &str| {
+            let value = value.trim();
+            value
+                .parse()
+                .ok()
+                .or_else(|| parse_timestamp(value))
+                .map(|ms| ms as f64)
+                .ok_or(())
+        };
+        let point = |pair: &str| {
+            let (from, to) = pair.split_once('=').ok_or(())?;
+            Ok((time(from)?, time(to)?))
+        };
+
+        let (first, second) = s.split_once(';').ok_or(())?;
+        Self::through(point(first)?, point(second)?).ok_or(())
+    }
+}
+
+// Frames to output, counted or enough to cover a duration so its last moment is shown
+pub fn frame_limit(
+    fps: FrameRate,
+    frame_count: Option<u64>,
+    duration_ms: Option<u64>,
+) -> Option<u64> {
+    frame_count.or_else(|| Some(fps.frame_at(duration_ms?, Rounding::Ceil)))
+}
+
+// Output without a frame limit stops after the last cue unless told otherwise, and
+// output with one runs to the limit
+pub fn exit_after_last_cue(frame_limit: Option<u64>, setting: Option<bool>) -> bool {
+    setting.unwrap_or(frame_limit.is_none())
+}
+
+// Decimal rates such as 29.97 and 23.976 stand for the NTSC rates 30000/1001 and 24000/1001
+fn ntsc(num: u64, den: u64) -> Option<(u64, u64)> {
+    let rate = num as f64 / den as f64;
+    let nominal = (rate * 1.001).round();
+    let ntsc_rate = nominal * 1000.0 / 1001.0;
+    ((ntsc_rate - rate).abs() < 0.01 && (nominal - rate).abs() >= 0.01)
+        .then_some((nominal as u64 * 1000, 1001))
+}
+
+fn gcd(a: u64, b: u64) -> u64 {
+    if b == 0 { a } else { gcd(b, a % b) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rate(s: &str) -> (u64, u64) {
+        let rate: FrameRate = s.parse().unwrap();
+        (rate.num, rate.den)
+    }
+
+    #[test]
+    fn parses_frame_rates() {
+        assert_eq!(rate("25"), (25, 1));
+        assert_eq!(rate("25.0"), (25, 1));
+        assert_eq!(rate("50/2"), (25, 1));
+        assert_eq!(rate("30000/1001"), (30000, 1001));
+        assert_eq!(rate("29.97"), (30000, 1001));
+        assert_eq!(rate("23.976"), (24000, 1001));
+        assert_eq!(rate("59.94"), (60000, 1001));
+        assert_eq!(rate("12.5"), (25, 2));
+        assert!("0".parse::<FrameRate>().is_err());
+        assert!("25/0".parse::<FrameRate>().is_err());
+        assert!("fast".parse::<FrameRate>().is_err());
+    }
+
+    #[test]
+    fn frame_start_times() {
+        assert_eq!(FrameRate::new(25, 1).frame_ms(25), 1000);
+        let ntsc = FrameRate::new(30000, 1001);
+        assert_eq!(ntsc.frame_ms(1), 33);
+        assert_eq!(ntsc.frame_ms(30), 1001);
+    }
+
+    #[test]
+    fn rounds_times_to_frames() {
+        let rate = FrameRate::new(25, 1);
+        // On a frame boundary every policy agrees
+        for rounding in [Rounding::Floor, Rounding::Nearest, Rounding::Ceil] {
+            assert_eq!(rate.frame_at(0, rounding), 0);
+            assert_eq!(rate.frame_at(40, rounding), 1);
+            assert_eq!(rate.frame_at(1000, rounding), 25);
+        }
+        // Just past a boundary
+        assert_eq!(rate.frame_at(41, Rounding::Floor), 1);
+        assert_eq!(rate.frame_at(41, Rounding::Nearest), 1);
+        assert_eq!(rate.frame_at(41, Rounding::Ceil), 2);
+        // Halfway between frames rounds up
+        assert_eq!(rate.frame_at(59, Rounding::Nearest), 1);
+        assert_eq!(rate.frame_at(60, Rounding::Nearest), 2);
+        // Just before a boundary
+        assert_eq!(rate.frame_at(79, Rounding::Floor), 1);
+        assert_eq!(rate.frame_at(79, Rounding::Nearest), 2);
+        assert_eq!(rate.frame_at(79, Rounding::Ceil), 2);
+    }
+
+    #[test]
+    fn frame_times_round_trip() {
+        for rate in [
+            FrameRate::new(25, 1),
+            FrameRate::new(30000, 1001),
+            FrameRate::new(24000, 1001),
+        ] {
+            for frame in 0..10_000 {
+                let ms = rate.frame_ms(frame);
+                assert_eq!(rate.frame_at(ms, Rounding::Ceil), frame);
+                assert_eq!(rate.frame_at(ms + 1, Rounding::Floor), frame);
+            }
+        }
+    }
+
+    #[test]
+    fn parses_two_point_sync() {
+        let retime: Retime = "1000=1500;11000=11500".parse().unwrap();
+        assert_eq!(retime.apply(0), 500);
+        assert_eq!(retime.apply(6000), 6500);
+
+        // 25 fps timings played at 23.976 fps run slower
+        let retime: Retime = "0=0;00:00:10,000=00:00:10,427".parse().unwrap();
+        assert_eq!(retime.apply(20_000), 20_854);
+        assert_eq!(retime.apply(u64::MAX), u64::MAX);
+        assert_eq!(retime.stretch(1000), 1043);
+
+        let retime: Retime = "1000=0;2000=1000".parse().unwrap();
+        assert_eq!(retime.apply(500), 0);
+
+        assert!("1000=0".parse::<Retime>().is_err());
+        assert!("1000=0;1000=5".parse::<Retime>().is_err());
+    }
+
+    #[test]
+    fn paces_frames() {
+        assert_eq!(FrameRate::new(25, 1).frame_time(25), Duration::from_secs(1));
+        let ntsc = FrameRate::new(30000, 1001);
+        assert_eq!(ntsc.frame_time(1).as_nanos(), 33_366_666);
+    }
+
+    #[test]
+    fn limits_output_length() {
+        let fps = FrameRate::new(25, 1);
+        assert_eq!(frame_limit(fps, Some(10), Some(1000)), Some(10));
+        assert_eq!(frame_limit(fps, None, Some(1000)), Some(25));
+        // A duration ending between frames takes in the frame it ends in
+        assert_eq!(frame_limit(fps, None, Some(1001)), Some(26));
+        assert_eq!(frame_limit(fps, None, Some(0)), Some(0));
+        assert_eq!(
+            frame_limit(FrameRate::new(30000, 1001), None, Some(1000)),
+            Some(30)
+        );
+        assert_eq!(frame_limit(fps, None, None), None);
+
+        assert!(exit_after_last_cue(None, None));
+        assert!(!exit_after_last_cue(Some(25), None));
+        assert!(exit_after_last_cue(Some(25), Some(true)));
+        assert!(!exit_after_last_cue(None, Some(false)));
+    }
+}
@@ -0,0 +1,487 @@
+use crate::color::parse_color;
+use crate::subtitle::{Align, Line, LinePosition, Placement, Run, Style, Subtitle};
+use roxmltree::{Document, Node};
+use std::collections::HashMap;
+
+const TTS: &str = "http://www.w3.org/ns/ttml#styling";
+const TTP: &str = "http://www.w3.org/ns/ttml#parameter";
+
+// Timing parameters declared on the root element
+struct Timing {
+    frame_rate: f64,
+    sub_frame_rate: f64,
+    tick_rate: f64,
+}
+
+impl Timing {
+    fn new(root: Node) -> Self {
+        let param = |name| root.attribute((TTP, name));
+        let frame_rate = param("frameRate").and_then(|r| r.parse().ok());
+        let multiplier = param("frameRateMultiplier")
+            .and_then(|m| {
+                let (num, den) = m.split_once(' ')?;
+                Some(num.trim().parse::<f64>().ok()? / den.trim().parse::<f64>().ok()?)
+            })
+            .unwrap_or(1.0);
+        let sub_frame_rate = param("subFrameRate")
+            .and_then(|r| r.parse().ok())
+            .unwrap_or(1.0);
+        let tick_rate =
+            param("tickRate")
+                .and_then(|r| r.parse().ok())
+                .unwrap_or(match frame_rate {
+                    Some(rate) => rate * sub_frame_rate,
+                    None => 1.0,
+                });
+
+        Self {
+            frame_rate: frame_rate.unwrap_or(30.0) * multiplier,
+            sub_frame_rate,
+            tick_rate,
+        }
+    }
+
+    // Clock time `hh:mm:ss[.fff]` or `hh:mm:ss:ff[.sub]`, or offset time such as `12.5s` or `900t`
+    fn parse(&self, value: &str) -> Option<f64> {
+        let value = value.trim();
+        if value.contains(':') {
+            let parts: Vec<&str> = value.split(':').collect();
+            let hours: f64 = parts[0].parse().ok()?;
+            let minutes: f64 = parts.get(1)?.parse().ok()?;
+            let seconds: f64 = parts.get(2)?.parse().ok()?;
+            let frames = match parts.get(3) {
+                Some(frames) => {
+                    let (frames, sub) = frames.split_once('.').unwrap_or((frames, "0"));
+                    frames.parse::<f64>().ok()? + sub.parse::<f64>().ok()? / self.sub_frame_rate
+                }
+                None => 0.0,
+            };
+            return Some(
+                (hours * 3600.0 + minutes * 60.0 + seconds + frames / self.frame_rate) * 1000.0,
+            );
+        }
+
+        let split = value.find(|c: char| c.is_ascii_alphabetic())?;
+        let (count, metric) = value.split_at(split);
+        let count: f64 = count.parse().ok()?;
+        let ms = match metric {
+            "h" => count * 3_600_000.0,
+            "m" => count * 60_000.0,
+            "s" => count * 1000.0,
+            "ms" => count,
+            "f" => count / self.frame_rate * 1000.0,
+            "t" => count / self.tick_rate * 1000.0,
+            _ => return None,
+        };
+        Some(ms)
+    }
+
+    // Resolve an element's active interval within its parent's
+    fn interval(&self, node: Node, parent: (f64, Option<f64>)) -> (f64, Option<f64>) {
+        let begin = parent.0
+            + node
+                .attribute("begin")
+                .and_then(|b| self.parse(b))
+                .unwrap_or(0.0);
+        let end = node
+            .attribute("end")
+            .and_then(|e| self.parse(e))
+            .map(|e| parent.0 + e)
+            .or_else(|| {
+                let dur = node.attribute("dur").and_then(|d| self.parse(d))?;
+                Some(begin + dur)
+            });
+        let end = match (end, parent.1) {
+            (Some(end), Some(parent_end)) => Some(end.min(parent_end)),
+            (end, parent_end) => end.or(parent_end),
+        };
+        (begin, end)
+    }
+}
+
+// Computed `tts:*` properties keyed by local name
+type Properties<'a> = HashMap<&'a str, &'a str>;
+
+struct Context<'a> {
+    timing: Timing,
+    styles: HashMap<&'a str, Node<'a, 'a>>,
+    regions: HashMap<&'a str, Node<'a, 'a>>,
+    // Root container size in pixels and cells, for lengths in `px` or `c`
+    extent: (f64, f64),
+    cells: (f64, f64),
+}
+
+pub fn parse_document(text: &str, frame: (i32, i32)) -> Result<Vec<Subtitle>, String> {
+    let document = Document::parse(text).map_err(|err| err.to_string())?;
+    let root = document.root_element();
+    if root.tag_name().name() != "tt" {
+        return Err("Missing tt root element".to_string());
+    }
+
+    let pair = |value: &str, unit: &str| {
+        let mut values = value
+            .split_whitespace()
+            .map(|v| v.strip_suffix(unit).unwrap_or(v).parse::<f64>().ok());
+        Some((values.next()??, values.next()??))
+    };
+    let mut context = Context {
+        timing: Timing::new(root),
+        styles: HashMap::new(),
+        regions: HashMap::new(),
+        extent: root
+            .attribute((TTS, "extent"))
+            .and_then(|e| pair(e, "px"))
+            .unwrap_or((frame.0 as f64, frame.1 as f64)),
+        cells: root
+            .attribute((TTP, "cellResolution"))
+            .and_then(|c| pair(c, ""))
+            .unwrap_or((32.0, 15.0)),
+    };
+    for node in root.descendants() {
+        let Some(id) = node.attribute((roxmltree::NS_XML_URI, "id")) else {
+            continue;
+        };
+        match node.tag_name().name() {
+            "style" => context.styles.insert(id, node),
+            "region" => context.regions.insert(id, node),
+            _ => None,
+        };
+    }
+
+    let mut cues = Vec::new();
+    if let Some(body) = root.children().find(|n| n.tag_name().name() == "body") {
+        context.walk(body, (0.0, None), &Properties::new(), None, &mut cues);
+    }
+    cues.sort_by_key(|cue| cue.start);
+
+    Ok(cues)
+}
+
+impl<'a> Context<'a> {
+    fn walk(
+        &self,
+        node: Node<'a, 'a>,
+        parent: (f64, Option<f64>),
+        inherited: &Properties<'a>,
+        region: Option<&'a str>,
+        cues: &mut Vec<Subtitle>,
+    ) {
+        let interval = self.timing.interval(node, parent);
+        let region = node.attribute("region").or(region);
+        let mut properties = inherited.clone();
+        self.apply_styles(node, &mut properties, 0);
+
+        if node.tag_name().name() == "p" {
+            cues.push(self.cue(node, interval, properties, region));
+            return;
+        }
+
+        // Backgrounds of body and div paint their block area, not the text
+        properties.remove("backgroundColor");
+        for child in node.children() {
+            if matches!(child.tag_name().name(), "div" | "p") {
+                self.walk(child, interval, &properties, region, cues);
+            }
+        }
+    }
+
+    // Referential styles first, then inline attributes, following style chains
+    fn apply_styles(&self, node: Node<'a, 'a>, properties: &mut Properties<'a>, depth: usize) {
+        if depth > 8 {
+            return;
+        }
+        for id in node.attribute("style").unwrap_or("").split_whitespace() {
+            if let Some(style) = self.styles.get(id) {
+                self.apply_styles(*style, properties, depth + 1);
+            }
+        }
+        for attribute in node.attributes() {
+            if attribute.namespace() == Some(TTS) {
+                properties.insert(attribute.name(), attribute.value());
+            }
+        }
+    }
+
+    fn cue(
+        &self,
+        p: Node<'a, 'a>,
+        (begin, end): (f64, Option<f64>),
+        properties: Properties<'a>,
+        region: Option<&'a str>,
+    ) -> Subtitle {
+        // Region styles sit beneath everything inherited through the body; text in a region
+        // that is not defined keeps the default placement
+        let mut computed = Properties::new();
+        if let Some(region) = region.and_then(|region| self.regions.get(region)) {
+            self.apply_styles(*region, &mut computed, 0);
+            computed.remove("backgroundColor");
+        }
+        computed.extend(properties);
+
+        let preserve = p
+            .ancestors()
+            .find_map(|n| n.attribute((roxmltree::NS_XML_URI, "space")))
+            == Some("preserve");
+        let mut lines = vec![Line::new()];
+        self.collect_text(p, &Properties::new(), &mut lines);
+        if !preserve {
+            lines.iter_mut().for_each(collapse_whitespace);
+        }
+
+        Subtitle {
+            start: begin.round() as u64,
+            end: end.map_or(u64::MAX, |end| end.round() as u64),
+            lines,
+            placement: self.placement(&computed),
+            style: style(&computed),
+            ..Default::default()
+        }
+    }
+
+    // Text becomes runs styled by the spans around it, on top of the cue style
+    fn collect_text(&self, node: Node<'a, 'a>, span: &Properties<'a>, lines: &mut Vec<Line>) {
+        for child in node.children() {
+            if let Some(text) = child.text().filter(|_| child.is_text()) {
+                let run = run(text, span);
+                let Some(line) = lines.last_mut() else {
+                    continue;
+                };
+                match line.last_mut() {
+                    Some(last) if last.same_look(&run) => last.text.push_str(&run.text),
+                    _ => line.push(run),
+                }
+                continue;
+            }
+            match child.tag_name().name() {
+                "br" => lines.push(Line::new()),
+                "span" => {
+                    let mut properties = span.clone();
+                    self.apply_styles(child, &mut properties, 0);
+                    self.collect_text(child, &properties, lines);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    // A region's origin and extent become the cue box, aligned within it
+    fn placement(&self, properties: &Properties) -> Placement {
+        let align = match properties.get("textAlign").copied() {
+            Some("center") => Align::Center,
+            Some("right" | "end") => Align::End,
+            _ => Align::Start,
+        };
+        let origin = properties.get("origin").and_then(|o| self.lengths(o));
+        let extent = properties.get("extent").and_then(|e| self.lengths(e));
+        if origin.is_none() && extent.is_none() {
+            return Placement {
+                align,
+                ..Default::default()
+            };
+        }
+
+        let (x, y) = origin.unwrap_or((0.0, 0.0));
+        let (width, height) = extent.unwrap_or((1.0 - x, 1.0 - y));
+        let display_align = match properties.get("displayAlign").copied() {
+            Some("center") => Align::Center,
+            Some("after") => Align::End,
+            _ => Align::Start,
+        };
+
+        Placement {
+            align,
+            position: Some((x, Align::Start)),
+            line: LinePosition::Fraction(y + height * display_align.factor(), display_align),
+            size: Some(width),
+            clamp: false,
+        }
+    }
+
+    // Two lengths as fractions of the root container
+    fn lengths(&self, value: &str) -> Option<(f32, f32)> {
+        let mut values = value.split_whitespace();
+        let x = self.length(values.next()?, self.extent.0, self.cells.0)?;
+        let y = self.length(values.next()?, self.extent.1, self.cells.1)?;
+        Some((x, y))
+    }
+
+    fn length(&self, value: &str, pixels: f64, cells: f64) -> Option<f32> {
+        let fraction = if let Some(percent) = value.strip_suffix('%') {
+            percent.parse::<f64>().ok()? / 100.0
+        } else if let Some(px) = value.strip_suffix("px") {
+            px.parse::<f64>().ok()? / pixels
+        } else if let Some(c) = value.strip_suffix('c') {
+            c.parse::<f64>().ok()? / cells
+        } else {
+            return None;
+        };
+        Some(fraction as f32)
+    }
+}
+
+fn run(text: &str, properties: &Properties) -> Run {
+    let color = |name| properties.get(name).and_then(|c| parse_color(c));
+    Run {
+        text: text.to_string(),
+        bold: properties.get("fontWeight").map(|w| *w == "bold"),
+        italic: properties
+            .get("fontStyle")
+            .map(|s| matches!(*s, "italic" | "oblique")),
+        underline: underline(properties),
+        color: color("color"),
+        background: color("backgroundColor"),
+        ..Default::default()
+    }
+}
+
+// Collapse whitespace across the runs of a line as `xml:space="default"` requires
+fn collapse_whitespace(line: &mut Line) {
+    let mut after_space = true;
+    for run in line.iter_mut() {
+        let mut text = String::with_capacity(run.text.len());
+        for c in run.text.chars() {
+            if !c.is_ascii_whitespace() {
+                text.push(c);
+                after_space = false;
+            } else if !after_space {
+                text.push(' ');
+                after_space = true;
+            }
+        }
+        run.text = text;
+    }
+    if let Some(last) = line.iter_mut().rev().find(|run| !run.text.is_empty())
+        && last.text.ends_with(' ')
+    {
+        last.text.pop();
+    }
+    line.retain(|run| !run.text.is_empty());
+}
+
+fn underline(properties: &Properties) -> bool {
+    properties
+        .get("textDecoration")
+        .is_some_and(|d| d.split_whitespace().any(|d| d == "underline"))
+}
+
+fn style(properties: &Properties) -> Style {
+    let color = |name| properties.get(name).and_then(|c| parse_color(c));
+    Style {
+        bold: properties.get("fontWeight") == Some(&"bold"),
+        italic: matches!(properties.get("fontStyle"), Some(&("italic" | "oblique"))),
+        underline: underline(properties),
+        color: color("color"),
+        background: color("backgroundColor"),
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Timing declared by `ttp:*` attributes on a root element
+    fn timing(parameters: &str) -> Timing {
+        let text = format!(
+            r#"<tt xmlns="http://www.w3.org/ns/ttml" xmlns:ttp="{}" {}/>"#,
+            TTP, parameters
+        );
+        Timing::new(Document::parse(&text).unwrap().root_element())
+    }
+
+    fn assert_ms(ms: Option<f64>, expected: f64) {
+        let ms = ms.unwrap();
+        assert!((ms - expected).abs() < 1e-6, "{} is not {}", ms, expected);
+    }
+
+    #[test]
+    fn parses_clock_times() {
+        let timing = timing(r#"ttp:frameRate="25" ttp:subFrameRate="2""#);
+        assert_ms(timing.parse("01:02:03.5"), 3_723_500.0);
+        assert_ms(timing.parse("00:00:01:12"), 1480.0);
+        // Half a frame on from frame 10
+        assert_ms(timing.parse("00:00:00:10.1"), 420.0);
+        assert_eq!(timing.parse("00:01"), None);
+    }
+
+    #[test]
+    fn applies_frame_rate_multiplier() {
+        let timing = timing(r#"ttp:frameRate="30" ttp:frameRateMultiplier="1000 1001""#);
+        assert_ms(timing.parse("00:00:01:15"), 1000.0 + 15.0 * 1001.0 / 30.0);
+        assert_ms(timing.parse("30f"), 1001.0);
+    }
+
+    #[test]
+    fn parses_offset_times() {
+        let timing = timing("");
+        assert_ms(timing.parse("1h"), 3_600_000.0);
+        assert_ms(timing.parse("2m"), 120_000.0);
+        assert_ms(timing.parse("1.5s"), 1500.0);
+        assert_ms(timing.parse("100ms"), 100.0);
+        // 30 fps unless declared
+        assert_ms(timing.parse("15f"), 500.0);
+        assert_eq!(timing.parse("5x"), None);
+        assert_eq!(timing.parse("soon"), None);
+    }
+
+    #[test]
+    fn parses_ticks() {
+        assert_ms(
+            timing(r#"ttp:tickRate="10000000""#).parse("5000000t"),
+            500.0,
+        );
+        // Without a tick rate, ticks are sub-frames, or seconds without a frame rate
+        assert_ms(
+            timing(r#"ttp:frameRate="25" ttp:subFrameRate="2""#).parse("100t"),
+            2000.0,
+        );
+        assert_ms(timing("").parse("3t"), 3000.0);
+    }
+
+    #[test]
+    fn nests_intervals_within_parents() {
+        let text = r#"<tt xmlns="http://www.w3.org/ns/ttml"><body begin="10s" end="20s"><div begin="1s" dur="3s"><p begin="1s" end="5s"/><p/></div><div begin="2s"/></body></tt>"#;
+        let document = Document::parse(text).unwrap();
+        let root = document.root_element();
+        let timing = Timing::new(root);
+        let element = |name| root.descendants().find(|n| n.has_tag_name(name)).unwrap();
+
+        let body = timing.interval(element("body"), (0.0, None));
+        assert_eq!(body, (10_000.0, Some(20_000.0)));
+        let div = timing.interval(element("div"), body);
+        assert_eq!(div, (11_000.0, Some(14_000.0)));
+
+        // Ends are clamped to the parent's, and missing ones taken from it
+        let mut paragraphs = root.descendants().filter(|n| n.has_tag_name("p"));
+        let p = timing.interval(paragraphs.next().unwrap(), div);
+        assert_eq!(p, (12_000.0, Some(14_000.0)));
+        let p = timing.interval(paragraphs.next().unwrap(), div);
+        assert_eq!(p, (11_000.0, Some(14_000.0)));
+
+        // Without any end the interval stays open
+        let late_div = root.descendants().filter(|n| n.has_tag_name("div")).nth(1);
+        assert_eq!(
+            timing.interval(late_div.unwrap(), (10_000.0, None)),
+            (12_000.0, None)
+        );
+    }
+
+    #[test]
+    fn places_text_in_undefined_regions_by_default() {
+        let text = r#"<tt xmlns="http://www.w3.org/ns/ttml"><body><p begin="0s" end="1s" region="missing">Text</p></body></tt>"#;
+        let cues = parse_document(text, (1920, 1080)).unwrap();
+        assert_eq!(cues.len(), 1);
+        assert!(cues[0].placement.position.is_none());
+        assert!(matches!(cues[0].placement.line, LinePosition::Auto));
+    }
+
+    #[test]
+    fn underlines_from_paragraphs_and_styles() {
+        let text = r#"<tt xmlns="http://www.w3.org/ns/ttml" xmlns:tts="http://www.w3.org/ns/ttml#styling"><head><styling><style xml:id="u" tts:textDecoration="underline"/></styling></head><body><div style="u"><p begin="0s" end="1s">Div</p></div><p begin="1s" end="2s" tts:textDecoration="underline">P</p><p begin="2s" end="3s">Plain <span tts:textDecoration="underline">span</span></p></body></tt>"#;
+        let cues = parse_document(text, (1920, 1080)).unwrap();
+        assert!(cues[0].style.underline);
+        assert!(cues[1].style.underline);
+        assert!(!cues[2].style.underline);
+        assert!(!cues[2].lines[0][0].underline && cues[2].lines[0][1].underline);
+    }
+}
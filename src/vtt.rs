@@ -0,0 +1,217 @@
+use crate::input::parse_timestamp;
+use crate::markup::{self, Dialect};
+use crate::subtitle::{Align, LinePosition, Placement, Subtitle};
+
+// Header, comment, style and region blocks carry no cue
+pub fn is_metadata(block: &[String]) -> bool {
+    let first = block[0].as_str();
+    ["WEBVTT", "NOTE", "STYLE", "REGION"].iter().any(|keyword| {
+        first
+            .strip_prefix(keyword)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with([' ', '\t']))
+    }) && !first.contains("-->")
+}
+
+// Parse a WebVTT cue block: an optional identifier, a timing line with settings and the payload
+pub fn parse_block(block: &[impl AsRef<str>]) -> Option<Subtitle> {
+    let mut rest = block.iter().map(|line| line.as_ref());
+    let mut timing = rest.next()?;
+    if !timing.contains("-->") {
+        timing = rest.next()?;
+    }
+
+    let (start, tail) = timing.split_once("-->")?;
+    let start = parse_timestamp(start)?;
+    let mut tail = tail.split_whitespace();
+    let end = parse_timestamp(tail.next()?)?;
+    let placement = parse_settings(tail);
+
+    // Cue timestamps are absolute, while runs are revealed relative to the cue start
+    let mut lines = markup::parse_lines(rest, Dialect::WebVtt);
+    for run in lines.iter_mut().flatten() {
+        run.reveal = run.reveal.map(|reveal| reveal.saturating_sub(start));
+    }
+
+    Some(Subtitle {
+        start,
+        end,
+        lines,
+        placement,
+        ..Default::default()
+    })
+}
+
+// More rows than any frame holds, which keeps line numbers counted from the other edge in range
+const MAX_LINE_NUMBER: i32 = 1000;
+
+fn parse_settings<'a>(settings: impl Iterator<Item = &'a str>) -> Placement {
+    let mut placement = Placement::default();
+    let mut position = None;
+    let mut position_align = None;
+
+    // Unknown or malformed settings are ignored as the spec requires
+    for setting in settings {
+        let Some((name, value)) = setting.split_once(':') else {
+            continue;
+        };
+        match name {
+            "align" => {
+                placement.align = match value {
+                    "start" | "left" => Align::Start,
+                    "center" => Align::Center,
+                    "end" | "right" => Align::End,
+                    _ => continue,
+                }
+            }
+            "position" => {
+                let (value, anchor) = value.split_once(',').unwrap_or((value, "auto"));
+                let Some(fraction) = parse_percentage(value) else {
+                    continue;
+                };
+                position = Some(fraction);
+                position_align = match anchor {
+                    "line-left" => Some(Align::Start),
+                    "center" => Some(Align::Center),
+                    "line-right" => Some(Align::End),
+                    _ => None,
+                };
+            }
+            "line" => {
+                let (value, anchor) = value.split_once(',').unwrap_or((value, "start"));
+                let anchor = match anchor {
+                    "center" => Align::Center,
+                    "end" => Align::End,
+                    _ => Align::Start,
+                };
+                if let Some(fraction) = parse_percentage(value) {
+                    placement.line = LinePosition::Fraction(fraction, anchor);
+                } else if let Ok(number) = value.parse::<i32>() {
+                    placement.line =
+                        LinePosition::Number(number.clamp(-MAX_LINE_NUMBER, MAX_LINE_NUMBER));
+                }
+            }
+            "size" => {
+                if let Some(fraction) = parse_percentage(value) {
+                    placement.size = Some(fraction);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // Position and its alignment default to the side the text is aligned to
+    let position = position.unwrap_or(placement.align.factor());
+    let position_align = position_align.unwrap_or(placement.align);
+    placement.position = Some((position, position_align));
+
+    // The cue box spans as far as the position allows unless sized explicitly
+    let max_size = match position_align {
+        Align::Start => 1.0 - position,
+        Align::Center => 2.0 * position.min(1.0 - position),
+        Align::End => position,
+    };
+    placement.size = Some(placement.size.unwrap_or(max_size).min(max_size));
+    placement.clamp = true;
+
+    placement
+}
+
+fn parse_percentage(value: &str) -> Option<f32> {
+    let percent: f32 = value.strip_suffix('%')?.parse().ok()?;
+    (0.0..=100.0).contains(&percent).then_some(percent / 100.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(settings: &str) -> Placement {
+        parse_settings(settings.split_whitespace())
+    }
+
+    #[test]
+    fn defaults_placement_to_the_alignment() {
+        let placement = settings("");
+        assert!(placement.align == Align::Center);
+        assert!(placement.position == Some((0.5, Align::Center)));
+        assert!(matches!(placement.line, LinePosition::Auto));
+        assert_eq!(placement.size, Some(1.0));
+
+        let placement = settings("align:start");
+        assert!(placement.align == Align::Start);
+        assert!(placement.position == Some((0.0, Align::Start)));
+        assert_eq!(placement.size, Some(1.0));
+
+        let placement = settings("align:right");
+        assert!(placement.position == Some((1.0, Align::End)));
+
+        // Unknown and malformed settings are ignored
+        let placement = settings("align:middle size:120% line:top position vertical:rl");
+        assert!(placement.align == Align::Center);
+        assert!(matches!(placement.line, LinePosition::Auto));
+        assert_eq!(placement.size, Some(1.0));
+    }
+
+    #[test]
+    fn parses_position_line_and_size() {
+        // A centred box spans as far as the nearer edge allows
+        let placement = settings("position:20%");
+        assert!(placement.position == Some((0.2, Align::Center)));
+        assert_eq!(placement.size, Some(0.4));
+
+        let placement = settings("position:25%,line-left size:50% align:start");
+        assert!(placement.position == Some((0.25, Align::Start)));
+        assert_eq!(placement.size, Some(0.5));
+
+        let placement = settings("position:80%,line-left size:50%");
+        let size = placement.size.unwrap();
+        assert!((size - 0.2).abs() < 1e-6);
+
+        let placement = settings("position:60%,line-right");
+        assert!(placement.position == Some((0.6, Align::End)));
+        assert_eq!(placement.size, Some(0.6));
+
+        assert!(matches!(settings("line:-2").line, LinePosition::Number(-2)));
+        assert!(matches!(settings("line:0").line, LinePosition::Number(0)));
+        assert!(matches!(
+            settings("line:-2147483648").line,
+            LinePosition::Number(-1000)
+        ));
+        assert!(matches!(
+            settings("line:10%").line,
+            LinePosition::Fraction(0.1, Align::Start)
+        ));
+        assert!(matches!(
+            settings("line:90%,end").line,
+            LinePosition::Fraction(0.9, Align::End)
+        ));
+    }
+
+    #[test]
+    fn parses_blocks() {
+        let sub =
+            parse_block(&["intro", "00:01.000 --> 00:02.000 line:1 align:end", "Hello"]).unwrap();
+        assert_eq!((sub.start, sub.end), (1000, 2000));
+        assert!(matches!(sub.placement.line, LinePosition::Number(1)));
+        assert!(sub.placement.align == Align::End);
+        assert_eq!(sub.lines[0][0].text, "Hello");
+
+        assert!(parse_block(&["intro", "Hello"]).is_none());
+        assert!(is_metadata(&["WEBVTT - Title".to_string()]));
+        assert!(is_metadata(&["NOTE".to_string()]));
+        assert!(!is_metadata(&["NOTES".to_string()]));
+        assert!(!is_metadata(&["NOTE --> ".to_string()]));
+    }
+
+    #[test]
+    fn reveals_runs_relative_to_the_cue_start() {
+        let sub = parse_block(&[
+            "00:01.000 --> 00:03.000",
+            "one <00:01.500>two",
+            "<00:02.250>three",
+        ])
+        .unwrap();
+        let reveals: Vec<_> = sub.lines.iter().flatten().map(|run| run.reveal).collect();
+        assert_eq!(reveals, [None, Some(500), Some(1250)]);
+    }
+}